@@ -4,11 +4,161 @@ fn main() {
         use std::env;
 
         // Get the Rust compiler version and set it as an environment variable.
-        let rustc_version = rustc_version::version().unwrap();
-        println!("cargo:rustc-env=RUBICON_RUSTC_VERSION={}", rustc_version);
+        let rustc_meta = rustc_version::version_meta().unwrap();
+        println!(
+            "cargo:rustc-env=RUBICON_RUSTC_VERSION={}",
+            rustc_meta.semver
+        );
+
+        // Two nightlies can share a version string, so also pass the exact build
+        // of the compiler, and the LLVM it uses.
+        let unknown = || "unknown".to_string();
+        println!(
+            "cargo:rustc-env=RUBICON_RUSTC_COMMIT_HASH={}",
+            rustc_meta.commit_hash.unwrap_or_else(unknown)
+        );
+        println!(
+            "cargo:rustc-env=RUBICON_RUSTC_COMMIT_DATE={}",
+            rustc_meta.commit_date.unwrap_or_else(unknown)
+        );
+        let channel = match rustc_meta.channel {
+            rustc_version::Channel::Dev => "dev",
+            rustc_version::Channel::Nightly => "nightly",
+            rustc_version::Channel::Beta => "beta",
+            rustc_version::Channel::Stable => "stable",
+        };
+        println!("cargo:rustc-env=RUBICON_RUSTC_CHANNEL={}", channel);
+        println!(
+            "cargo:rustc-env=RUBICON_LLVM_VERSION={}",
+            rustc_meta
+                .llvm_version
+                .map(|v| v.to_string())
+                .unwrap_or_else(unknown)
+        );
 
         // Pass the target triple.
         let target = env::var("TARGET").unwrap();
         println!("cargo:rustc-env=RUBICON_TARGET_TRIPLE={}", target);
+
+        // Pass the enabled target features, and a hash of the flags that may change
+        // codegen or layout (e.g. `-C target-cpu`, `-Z randomize-layout`)
+        let target_features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+        println!(
+            "cargo:rustc-env=RUBICON_TARGET_FEATURES={}",
+            if target_features.is_empty() {
+                "none"
+            } else {
+                &target_features
+            }
+        );
+
+        let rustflags = env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+        println!(
+            "cargo:rustc-env=RUBICON_RUSTFLAGS_HASH={}",
+            rustflags_hash(&rustflags)
+        );
+    }
+}
+
+/// Hashes the codegen-affecting flags out of `CARGO_ENCODED_RUSTFLAGS`, so that
+/// shared objects built with different `RUSTFLAGS` are detected.
+///
+/// Flags are normalized first: `-C opt`, `-Copt` and `--codegen=opt` are the same
+/// flag, order doesn't matter, and flags that only affect linking, lints or
+/// diagnostics (e.g. the `-C link-arg` needed to build modules on macOS) are left
+/// out. No flags at all hash to `none`.
+///
+/// The hash is FNV-1a, since it must be the same no matter which rustc built the
+/// build script.
+#[cfg(any(feature = "export-globals", feature = "import-globals"))]
+fn rustflags_hash(encoded: &str) -> String {
+    const IGNORED_CODEGEN_OPTS: &[&str] = &[
+        "link-arg",
+        "link-args",
+        "link-dead-code",
+        "link-self-contained",
+        "linker",
+        "linker-flavor",
+        "linker-plugin-lto",
+        "prefer-dynamic",
+        "rpath",
+        "incremental",
+        "debuginfo",
+        "split-debuginfo",
+        "strip",
+        "save-temps",
+        "remark",
+    ];
+    const IGNORED_FLAGS_WITH_VALUE: &[&str] = &[
+        "-L",
+        "-l",
+        "-A",
+        "-W",
+        "-D",
+        "-F",
+        "--cap-lints",
+        "--error-format",
+        "--json",
+        "--color",
+        "--diagnostic-width",
+        "--remap-path-prefix",
+        "--check-cfg",
+    ];
+
+    let mut normalized = Vec::new();
+    let mut flags = encoded.split('\x1f').filter(|flag| !flag.is_empty());
+    while let Some(flag) = flags.next() {
+        // `-C opt` / `-Copt` / `--codegen opt` / `--codegen=opt` all become `-Copt`,
+        // and likewise for `-Z`
+        let (prefix, rest) = match flag {
+            "-C" | "--codegen" => ("-C", flags.next().unwrap_or_default()),
+            "-Z" => ("-Z", flags.next().unwrap_or_default()),
+            _ => match flag
+                .strip_prefix("--codegen=")
+                .or_else(|| flag.strip_prefix("-C"))
+            {
+                Some(rest) => ("-C", rest),
+                None => match flag.strip_prefix("-Z") {
+                    Some(rest) => ("-Z", rest),
+                    None => ("", flag),
+                },
+            },
+        };
+
+        if prefix == "-C" {
+            let name = rest.split('=').next().unwrap_or_default();
+            if IGNORED_CODEGEN_OPTS.contains(&name) {
+                continue;
+            }
+        } else if prefix.is_empty() {
+            let ignored = IGNORED_FLAGS_WITH_VALUE
+                .iter()
+                .find(|ignored| flag.starts_with(**ignored));
+            if let Some(ignored) = ignored {
+                // the value may be the next flag, e.g. `-L path`
+                if flag == *ignored {
+                    flags.next();
+                }
+                continue;
+            }
+        }
+
+        normalized.push(format!("{prefix}{rest}"));
+    }
+
+    if normalized.is_empty() {
+        return "none".to_string();
+    }
+
+    normalized.sort();
+    normalized.dedup();
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for flag in &normalized {
+        for byte in flag.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
     }
+    format!("{hash:016x}")
 }
@@ -0,0 +1,349 @@
+//! Sharing the host's global allocator with modules.
+//!
+//! Every cdylib links its own copy of the standard library, and with it, its own
+//! global allocator. Memory allocated by one shared object and freed by another is
+//! fine as long as they both end up calling the same `malloc`, and heap corruption
+//! otherwise, e.g. if the host has a `#[global_allocator]` and modules don't.
+//!
+//! The host declares its allocator with `global_allocator!`, which publishes a table
+//! of functions forwarding to it through a slot exported with the other globals.
+//! [`SharedAllocator`] forwards to whatever is in that slot.
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// The host's global allocator, as seen by modules. Only made of `extern "C"`
+/// functions, since that's the one thing two shared objects can always agree on.
+#[doc(hidden)]
+#[repr(C)]
+pub struct AllocatorTable {
+    alloc: unsafe extern "C" fn(size: usize, align: usize) -> *mut u8,
+    alloc_zeroed: unsafe extern "C" fn(size: usize, align: usize) -> *mut u8,
+    dealloc: unsafe extern "C" fn(ptr: *mut u8, size: usize, align: usize),
+    realloc:
+        unsafe extern "C" fn(ptr: *mut u8, size: usize, align: usize, new_size: usize) -> *mut u8,
+}
+
+impl AllocatorTable {
+    pub const fn new(
+        alloc: unsafe extern "C" fn(usize, usize) -> *mut u8,
+        alloc_zeroed: unsafe extern "C" fn(usize, usize) -> *mut u8,
+        dealloc: unsafe extern "C" fn(*mut u8, usize, usize),
+        realloc: unsafe extern "C" fn(*mut u8, usize, usize, usize) -> *mut u8,
+    ) -> Self {
+        Self {
+            alloc,
+            alloc_zeroed,
+            dealloc,
+            realloc,
+        }
+    }
+}
+
+// Owned by the exporter, like the mismatch handler. A null pointer means "use the
+// system allocator".
+#[cfg(feature = "export-globals")]
+#[export_name = "RUBICON_GLOBAL_ALLOCATOR__RUBICON_EXPORT"]
+static RUBICON_GLOBAL_ALLOCATOR: AtomicPtr<AllocatorTable> = AtomicPtr::new(std::ptr::null_mut());
+
+#[cfg(all(feature = "import-globals", not(feature = "import-globals-dlsym")))]
+extern "Rust" {
+    #[link_name = "RUBICON_GLOBAL_ALLOCATOR__RUBICON_EXPORT"]
+    static RUBICON_GLOBAL_ALLOCATOR: AtomicPtr<AllocatorTable>;
+}
+
+#[cfg(not(any(feature = "export-globals", feature = "import-globals")))]
+static RUBICON_GLOBAL_ALLOCATOR: AtomicPtr<AllocatorTable> = AtomicPtr::new(std::ptr::null_mut());
+
+#[cfg(not(feature = "import-globals-dlsym"))]
+#[inline(always)]
+fn shared_allocator_slot() -> &'static AtomicPtr<AllocatorTable> {
+    #[allow(unused_unsafe)]
+    unsafe {
+        &RUBICON_GLOBAL_ALLOCATOR
+    }
+}
+
+// Looked up on the first allocation. Nothing may panic (or even print) from inside
+// an allocator, so if nothing exports the slot, the module keeps to the system
+// allocator: without an exporter, there's nobody to exchange memory with anyway.
+#[cfg(feature = "import-globals-dlsym")]
+fn shared_allocator_slot() -> &'static AtomicPtr<AllocatorTable> {
+    static LOCAL: AtomicPtr<AllocatorTable> = AtomicPtr::new(std::ptr::null_mut());
+    static SLOT: AtomicPtr<AtomicPtr<AllocatorTable>> = AtomicPtr::new(std::ptr::null_mut());
+
+    let mut slot = SLOT.load(Ordering::Acquire);
+    if slot.is_null() {
+        slot = crate::import::lookup("RUBICON_GLOBAL_ALLOCATOR__RUBICON_EXPORT\0")
+            .map_or(&LOCAL as *const _ as *mut _, |address| address as *mut _);
+        SLOT.store(slot, Ordering::Release);
+    }
+    unsafe { &*slot }
+}
+
+/// Called by `global_allocator!` before `main`.
+#[doc(hidden)]
+pub fn set_shared_allocator(table: &'static AllocatorTable) {
+    shared_allocator_slot().store(table as *const _ as *mut _, Ordering::Release);
+}
+
+#[inline(always)]
+fn shared_allocator() -> Option<&'static AllocatorTable> {
+    let table = shared_allocator_slot().load(Ordering::Acquire);
+    (!table.is_null()).then(|| unsafe { &*table })
+}
+
+/// Declares the host's `#[global_allocator]`, and shares it with every module that
+/// uses [`SharedAllocator`], so that memory can be allocated by one shared object and
+/// freed by another.
+///
+/// ```rust
+/// # use std::alloc::{GlobalAlloc, Layout, System};
+/// struct MyAllocator;
+///
+/// unsafe impl GlobalAlloc for MyAllocator {
+///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+///         System.alloc(layout)
+///     }
+///
+///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+///         System.dealloc(ptr, layout)
+///     }
+/// }
+///
+/// rubicon::global_allocator! {
+///     static ALLOCATOR: MyAllocator = MyAllocator;
+/// }
+/// ```
+///
+/// The allocator is shared from a constructor, before `main` runs, and so before any
+/// module is loaded. This goes in the binary, not in the `exports` dylib: that's where
+/// `#[global_allocator]` has to be.
+#[cfg(not(feature = "import-globals"))]
+#[macro_export]
+macro_rules! global_allocator {
+    ($(#[$attrs:meta])* $vis:vis static $name:ident: $ty:ty = $expr:expr $(;)?) => {
+        $(#[$attrs])*
+        #[global_allocator]
+        $vis static $name: $ty = $expr;
+
+        const _: () = {
+            use ::std::alloc::{GlobalAlloc, Layout};
+
+            unsafe extern "C" fn alloc(size: usize, align: usize) -> *mut u8 {
+                GlobalAlloc::alloc(&$name, Layout::from_size_align_unchecked(size, align))
+            }
+            unsafe extern "C" fn alloc_zeroed(size: usize, align: usize) -> *mut u8 {
+                GlobalAlloc::alloc_zeroed(&$name, Layout::from_size_align_unchecked(size, align))
+            }
+            unsafe extern "C" fn dealloc(ptr: *mut u8, size: usize, align: usize) {
+                GlobalAlloc::dealloc(&$name, ptr, Layout::from_size_align_unchecked(size, align))
+            }
+            unsafe extern "C" fn realloc(ptr: *mut u8, size: usize, align: usize, new_size: usize) -> *mut u8 {
+                GlobalAlloc::realloc(&$name, ptr, Layout::from_size_align_unchecked(size, align), new_size)
+            }
+            static TABLE: $crate::AllocatorTable = $crate::AllocatorTable::new(alloc, alloc_zeroed, dealloc, realloc);
+
+            #[used]
+            #[cfg_attr(
+                any(target_os = "linux", target_os = "android", target_os = "freebsd"),
+                link_section = ".init_array"
+            )]
+            #[cfg_attr(target_vendor = "apple", link_section = "__DATA,__mod_init_func")]
+            #[cfg_attr(windows, link_section = ".CRT$XCU")]
+            static SHARE_ALLOCATOR: extern "C" fn() = {
+                extern "C" fn share_allocator() {
+                    $crate::set_shared_allocator(&TABLE);
+                }
+                share_allocator
+            };
+        };
+    };
+}
+
+/// A [`GlobalAlloc`] that forwards to the host's global allocator, as declared with
+/// `global_allocator!`, so that memory can be allocated by one shared object and
+/// freed by another.
+///
+/// Modules opt into it with:
+///
+// importing needs an exporter to link against, and doctests don't have one
+#[cfg_attr(not(feature = "import-globals"), doc = "```rust")]
+#[cfg_attr(feature = "import-globals", doc = "```rust,ignore")]
+/// #[global_allocator]
+/// static ALLOCATOR: rubicon::SharedAllocator = rubicon::SharedAllocator;
+/// ```
+///
+/// If the host didn't share its allocator (or, with `import-globals-dlsym`, if nothing
+/// exports globals at all), it forwards to [`std::alloc::System`], which is also what
+/// the host uses unless it says otherwise.
+pub struct SharedAllocator;
+
+unsafe impl GlobalAlloc for SharedAllocator {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match shared_allocator() {
+            Some(table) => (table.alloc)(layout.size(), layout.align()),
+            None => std::alloc::System.alloc(layout),
+        }
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        match shared_allocator() {
+            Some(table) => (table.alloc_zeroed)(layout.size(), layout.align()),
+            None => std::alloc::System.alloc_zeroed(layout),
+        }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match shared_allocator() {
+            Some(table) => (table.dealloc)(ptr, layout.size(), layout.align()),
+            None => std::alloc::System.dealloc(ptr, layout),
+        }
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        match shared_allocator() {
+            Some(table) => (table.realloc)(ptr, layout.size(), layout.align(), new_size),
+            None => std::alloc::System.realloc(ptr, layout, new_size),
+        }
+    }
+}
+
+/// A [`GlobalAlloc`] wrapper that catches memory freed by another allocator than the
+/// one that allocated it, typically a `Box` created in one module and dropped in
+/// another, when they don't share their allocator (see [`SharedAllocator`]).
+///
+/// ```rust
+/// #[global_allocator]
+/// static ALLOCATOR: rubicon::DebugAllocator<std::alloc::System> =
+///     rubicon::DebugAllocator::new(std::alloc::System);
+/// ```
+///
+/// Every allocation is tagged with the address of the `DebugAllocator` that made it,
+/// which says which shared object it belongs to. When memory is freed by another
+/// `DebugAllocator`, or wasn't allocated by one at all, the report names both shared
+/// objects (as found by `dladdr`, on unix-like systems, whatever rubicon's features)
+/// and the process aborts: freeing it anyway would corrupt the heap.
+///
+/// Tags take up to `max(16, align)` bytes per allocation, and telling foreign memory
+/// apart means reading the 16 bytes before it: this is a debugging aid, not something
+/// to ship.
+pub struct DebugAllocator<A> {
+    inner: A,
+}
+
+impl<A> DebugAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+const DEBUG_TAG_MAGIC: usize = 0x7275_6269_636f_6e21_u64 as usize; // "rubicon!"
+const DEBUG_TAG_SIZE: usize = 2 * std::mem::size_of::<usize>();
+
+/// Stored right before every allocation made by a [`DebugAllocator`].
+#[repr(C)]
+struct DebugTag {
+    magic: usize,
+    owner: usize,
+}
+
+impl<A: GlobalAlloc> DebugAllocator<A> {
+    /// The layout of the allocation, tag included, and the offset of the memory
+    /// handed out.
+    #[inline]
+    fn tagged(layout: Layout) -> Option<(Layout, usize)> {
+        let align = layout.align().max(std::mem::align_of::<DebugTag>());
+        let offset = DEBUG_TAG_SIZE.max(align);
+        let size = layout.size().checked_add(offset)?;
+        Some((Layout::from_size_align(size, align).ok()?, offset))
+    }
+
+    #[inline]
+    fn owner(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    #[inline]
+    unsafe fn tag(&self, base: *mut u8, offset: usize) -> *mut u8 {
+        if base.is_null() {
+            return base;
+        }
+        let ptr = base.add(offset);
+        ptr.cast::<DebugTag>().sub(1).write(DebugTag {
+            magic: DEBUG_TAG_MAGIC,
+            owner: self.owner(),
+        });
+        ptr
+    }
+
+    #[cold]
+    fn report_foreign_free(&self, ptr: *mut u8, owner: Option<usize>) -> ! {
+        use std::io::Write;
+
+        let name = |address: usize| {
+            crate::report::shared_object_path(address as *const std::ffi::c_void)
+                .and_then(|path| path.to_str().ok())
+                .unwrap_or("an unknown shared object")
+        };
+        // no allocating here: we're inside the allocator
+        let mut stderr = std::io::stderr().lock();
+        let _ = match owner {
+            Some(owner) => writeln!(
+                stderr,
+                "rubicon: {} frees memory at {:p} that was allocated by {}, with another allocator",
+                name(self.owner()),
+                ptr,
+                name(owner),
+            ),
+            None => writeln!(
+                stderr,
+                "rubicon: {} frees memory at {:p} that wasn't allocated by a rubicon::DebugAllocator (maybe by a shared object that doesn't use one?)",
+                name(self.owner()),
+                ptr,
+            ),
+        };
+        let _ = writeln!(
+            stderr,
+            "rubicon: memory must be freed by the allocator that allocated it, see rubicon::SharedAllocator. Aborting"
+        );
+        std::process::abort();
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for DebugAllocator<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some((tagged, offset)) = Self::tagged(layout) else {
+            return std::ptr::null_mut();
+        };
+        self.tag(self.inner.alloc(tagged), offset)
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let Some((tagged, offset)) = Self::tagged(layout) else {
+            return std::ptr::null_mut();
+        };
+        self.tag(self.inner.alloc_zeroed(tagged), offset)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let tag = ptr.cast::<DebugTag>().sub(1).read();
+        if tag.magic != DEBUG_TAG_MAGIC {
+            self.report_foreign_free(ptr, None);
+        }
+        if tag.owner != self.owner() {
+            self.report_foreign_free(ptr, Some(tag.owner));
+        }
+
+        // `layout` is the one `alloc` was called with, so this can't fail
+        let (tagged, offset) = Self::tagged(layout).unwrap_unchecked();
+        self.inner.dealloc(ptr.sub(offset), tagged);
+    }
+}
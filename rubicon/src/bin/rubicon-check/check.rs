@@ -0,0 +1,110 @@
+//! `rubicon-check [check] <host> [module]...`: compares compatibility tables.
+
+use std::path::Path;
+
+use crate::elf::{COMPATIBILITY_INFO_SUFFIX, EXPECTED_SUFFIX};
+use crate::LoadedObject;
+
+/// Returns `Ok(false)` if any module is incompatible.
+pub fn run(args: &[String]) -> Result<bool, String> {
+    // the host, and whatever it depends on that lives next to it
+    let exporters = LoadedObject::load_with_neighbours(Path::new(&args[0]))?;
+
+    for exporter in &exporters {
+        println!(
+            "exporter {} ({} rubicon symbols)",
+            exporter.path.display(),
+            exporter.info.exports.len()
+        );
+    }
+
+    let mut all_good = true;
+    for module_path in &args[1..] {
+        let module = LoadedObject::load(Path::new(module_path))?;
+        println!();
+        println!("module {}", module.path.display());
+        if !check_module(&module, &exporters) {
+            all_good = false;
+        }
+    }
+
+    println!();
+    if all_good {
+        println!("✅ all modules are compatible");
+    } else {
+        println!("❌ some modules are incompatible");
+    }
+    Ok(all_good)
+}
+
+fn check_module<'a>(module: &'a LoadedObject, exporters: &'a [LoadedObject]) -> bool {
+    let mut ok = true;
+
+    let mut expected_tables: Vec<_> = module
+        .info
+        .tables
+        .iter()
+        .filter(|(name, _)| name.ends_with(EXPECTED_SUFFIX))
+        .collect();
+    expected_tables.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, imported) in expected_tables {
+        // along with its semver-compatible version, e.g. `mokio_0_1`
+        let crate_name = &name[..name.len() - EXPECTED_SUFFIX.len()];
+        let exported_name = format!("{crate_name}{COMPATIBILITY_INFO_SUFFIX}");
+        let Some((exporter, exported)) = exporters.iter().find_map(|exporter| {
+            exporter
+                .info
+                .tables
+                .get(&exported_name)
+                .map(|table| (exporter, table))
+        }) else {
+            // reported below as a missing import
+            continue;
+        };
+
+        let as_pairs = |table: &'a [(String, String)]| -> Vec<(&'a str, &'a str)> {
+            table
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect()
+        };
+        let exported = rubicon::build::expand_compatibility_info(&as_pairs(exported));
+        let imported = rubicon::build::expand_compatibility_info(&as_pairs(imported));
+
+        let report = rubicon::CompatibilityReport::compare(
+            crate_name,
+            None,
+            &exported,
+            &imported,
+            module.path.display().to_string(),
+            exporter.name(),
+        );
+        match report {
+            Some(report) => {
+                ok = false;
+                println!("  ❌ {}: incompatible", crate_name);
+                println!("{}", report);
+            }
+            None => println!("  ✅ {}: compatible", crate_name),
+        }
+    }
+
+    let mut missing: Vec<&String> = module
+        .info
+        .imports
+        .iter()
+        .filter(|import| {
+            !exporters
+                .iter()
+                .any(|exporter| exporter.info.exports.contains(import))
+        })
+        .collect();
+    missing.sort();
+    for import in missing {
+        ok = false;
+        println!("  ❌ {}: imported, but not exported by the host", import);
+    }
+
+    ok
+}
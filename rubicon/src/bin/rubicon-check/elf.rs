@@ -0,0 +1,243 @@
+//! Just enough ELF parsing to find rubicon's symbols and read its tables.
+
+use std::collections::HashMap;
+
+use object::elf;
+use object::read::elf::{Dyn, ElfFile, FileHeader};
+use object::{
+    Endianness, FileKind, Object, ObjectSection, ObjectSymbol, ObjectSymbolTable, RelocationTarget,
+    SectionKind,
+};
+
+/// A symbol is considered part of rubicon's business if it ends with one of these.
+const RUBICON_SUFFIXES: &[&str] = &[
+    "__RUBICON_EXPORT",
+    "__RUBICON_FINGERPRINT",
+    COMPATIBILITY_INFO_SUFFIX,
+];
+
+/// Suffix of the table exported alongside a crate's globals, after the crate's name and
+/// semver-compatible version (e.g. `mokio_0_1__compatibility_info`).
+pub const COMPATIBILITY_INFO_SUFFIX: &str = "__compatibility_info";
+
+/// Suffix of the table a shared object expects the exporter's `__compatibility_info` to match.
+pub const EXPECTED_SUFFIX: &str = "__compatibility_info_expected";
+
+/// Suffix of the table describing an exported global (crate, kind, type).
+pub const INFO_SUFFIX: &str = "__RUBICON_INFO";
+
+/// Everything `rubicon-check` needs to know about a shared object (or executable).
+pub struct ObjectInfo {
+    /// Rubicon symbols defined (exported) by the object
+    pub exports: Vec<String>,
+
+    /// Rubicon symbols the object needs from another object
+    pub imports: Vec<String>,
+
+    /// `<crate>__compatibility_info`, `<crate>__compatibility_info_expected` and
+    /// `<global>__RUBICON_INFO` tables, by symbol name
+    pub tables: HashMap<String, Vec<(String, String)>>,
+
+    /// Shared libraries the object depends on (`DT_NEEDED`)
+    pub needed: Vec<String>,
+}
+
+pub fn parse(data: &[u8]) -> Result<ObjectInfo, String> {
+    match FileKind::parse(data).map_err(|e| e.to_string())? {
+        FileKind::Elf32 => parse_elf::<elf::FileHeader32<Endianness>>(data),
+        FileKind::Elf64 => parse_elf::<elf::FileHeader64<Endianness>>(data),
+        kind => Err(format!("only ELF files are supported, this is {:?}", kind)),
+    }
+}
+
+fn parse_elf<Elf: FileHeader<Endian = Endianness>>(data: &[u8]) -> Result<ObjectInfo, String> {
+    let file = ElfFile::<Elf>::parse(data).map_err(|e| e.to_string())?;
+    let reader = Reader::new(&file);
+
+    let mut info = ObjectInfo {
+        exports: Vec::new(),
+        imports: Vec::new(),
+        tables: HashMap::new(),
+        needed: Vec::new(),
+    };
+
+    // executables may not have a dynamic symbol table, so look at both
+    for symbol in file.dynamic_symbols().chain(file.symbols()) {
+        let Ok(name) = symbol.name() else { continue };
+        let is_table = name.ends_with(COMPATIBILITY_INFO_SUFFIX)
+            || name.ends_with(EXPECTED_SUFFIX)
+            || name.ends_with(INFO_SUFFIX);
+        if !is_table && !RUBICON_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+            continue;
+        }
+
+        if symbol.is_undefined() {
+            if !info.imports.iter().any(|n| n == name) {
+                info.imports.push(name.to_string());
+            }
+            continue;
+        }
+
+        let is_description = name.ends_with(EXPECTED_SUFFIX) || name.ends_with(INFO_SUFFIX);
+        if !is_description && !info.exports.iter().any(|n| n == name) {
+            info.exports.push(name.to_string());
+        }
+        if is_table && !info.tables.contains_key(name) {
+            let table = reader
+                .read_table(symbol.address())
+                .map_err(|e| format!("could not read {}: {}", name, e))?;
+            info.tables.insert(name.to_string(), table);
+        }
+    }
+
+    let endian = file.endian();
+    let sections = file.elf_section_table();
+    if let Some((entries, link)) = sections.dynamic(endian, data).map_err(|e| e.to_string())? {
+        let strings = sections
+            .strings(endian, data, link)
+            .map_err(|e| e.to_string())?;
+        for entry in entries {
+            if entry.tag32(endian) == Some(elf::DT_NEEDED) {
+                let name = entry.string(endian, strings).map_err(|e| e.to_string())?;
+                info.needed.push(String::from_utf8_lossy(name).into_owned());
+            }
+        }
+    }
+
+    Ok(info)
+}
+
+/// Where the dynamic loader points a word, relative to the load address.
+struct Relocation {
+    target: u64,
+    /// `SHT_REL` relocations (e.g. on i686 and 32-bit ARM) don't carry their addend:
+    /// it's whatever the word holds in the file.
+    addend: Option<i64>,
+}
+
+/// Reads pointers and strings out of a file, as they would be once loaded.
+struct Reader<'a> {
+    sections: Vec<(u64, &'a [u8])>,
+    /// Dynamic relocations, by address: in a shared object, pointers are only
+    /// filled in by the dynamic loader, from these.
+    relocations: HashMap<u64, Relocation>,
+    pointer_size: usize,
+    little_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new<Elf: FileHeader<Endian = Endianness>>(file: &'a ElfFile<'a, Elf>) -> Self {
+        let sections = file
+            .sections()
+            .filter(|section| section.kind() != SectionKind::UninitializedData)
+            .filter_map(|section| Some((section.address(), section.data().ok()?)))
+            .collect();
+
+        let mut relocations = HashMap::new();
+        let dynamic_symbols = file.dynamic_symbol_table();
+        if let (Some(iter), Some(dynamic_symbols)) = (file.dynamic_relocations(), dynamic_symbols) {
+            for (address, relocation) in iter {
+                let target = match relocation.target() {
+                    // e.g. `R_X86_64_RELATIVE`: relative to the load address
+                    RelocationTarget::Absolute => 0,
+                    RelocationTarget::Symbol(index) => match dynamic_symbols.symbol_by_index(index)
+                    {
+                        Ok(symbol) if !symbol.is_undefined() => symbol.address(),
+                        // resolved against another object, can't follow it
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                let addend = if relocation.has_implicit_addend() {
+                    None
+                } else {
+                    Some(relocation.addend())
+                };
+                relocations.insert(address, Relocation { target, addend });
+            }
+        }
+
+        Self {
+            sections,
+            relocations,
+            pointer_size: if file.is_64() { 8 } else { 4 },
+            little_endian: file.is_little_endian(),
+        }
+    }
+
+    fn bytes(&self, address: u64, len: usize) -> Result<&'a [u8], String> {
+        for &(start, data) in &self.sections {
+            if address >= start && address - start < data.len() as u64 {
+                let offset = (address - start) as usize;
+                return data
+                    .get(offset..offset + len)
+                    .ok_or_else(|| format!("{len} bytes at {address:#x} overflow their section"));
+            }
+        }
+        Err(format!("address {address:#x} isn't in any section"))
+    }
+
+    fn word(&self, address: u64) -> Result<u64, String> {
+        match self.relocations.get(&address) {
+            Some(Relocation {
+                target,
+                addend: Some(addend),
+            }) => Ok(target.wrapping_add(*addend as u64)),
+            Some(Relocation {
+                target,
+                addend: None,
+            }) => Ok(target.wrapping_add(self.raw_word(address)?)),
+            None => self.raw_word(address),
+        }
+    }
+
+    /// The word at `address`, as stored in the file.
+    fn raw_word(&self, address: u64) -> Result<u64, String> {
+        let bytes = self.bytes(address, self.pointer_size)?;
+        let mut buf = [0u8; 8];
+        if self.little_endian {
+            buf[..bytes.len()].copy_from_slice(bytes);
+            Ok(u64::from_le_bytes(buf))
+        } else {
+            buf[8 - bytes.len()..].copy_from_slice(bytes);
+            Ok(u64::from_be_bytes(buf))
+        }
+    }
+
+    /// Reads a pointer followed by a length, as laid out by rubicon's `#[repr(C)]` types.
+    fn pointer_and_len(&self, address: u64) -> Result<(u64, u64), String> {
+        Ok((
+            self.word(address)?,
+            self.word(address + self.pointer_size as u64)?,
+        ))
+    }
+
+    fn str(&self, address: u64) -> Result<String, String> {
+        let (ptr, len) = self.pointer_and_len(address)?;
+        if len == 0 {
+            return Ok(String::new());
+        }
+        if len > 64 * 1024 {
+            return Err(format!("implausible string length {len} at {address:#x}"));
+        }
+        let bytes = self.bytes(ptr, len as usize)?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    /// Reads a `rubicon::InfoTable` static at `address`.
+    fn read_table(&self, address: u64) -> Result<Vec<(String, String)>, String> {
+        let (ptr, len) = self.pointer_and_len(address)?;
+        if len > 4096 {
+            return Err(format!("implausible table length {len}"));
+        }
+
+        let pointer_size = self.pointer_size as u64;
+        let entry_size = 4 * pointer_size;
+        (0..len)
+            .map(|i| {
+                let entry = ptr + i * entry_size;
+                Ok((self.str(entry)?, self.str(entry + 2 * pointer_size)?))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,105 @@
+//! Checks that a host and its modules agree on the crates they share, without
+//! running anything.
+//!
+//! ```text
+//! rubicon-check [check] <host> [module]...
+//! rubicon-check symbols <object>...
+//! ```
+//!
+//! The host is the executable (or shared object) that exports globals. Its
+//! dependencies (`DT_NEEDED`) found next to it are read too, since that's typically
+//! where the globals actually live (e.g. a `dylib` crate named `exports`).
+//!
+//! For every module, `rubicon-check` compares the `<crate>_<version>__compatibility_info`
+//! tables exported by the host with the ones the module expects, printing the same
+//! table as `compatibility_check!` does at runtime, and lists the globals the module
+//! imports but nothing exports. It exits with status 1 if anything is amiss, which
+//! makes it suitable for CI.
+//!
+//! `rubicon-check symbols` lists the globals each object exports (with their crate,
+//! and whether they're thread-locals or process-locals) and imports (with the object
+//! that exports them, if any).
+
+mod check;
+mod elf;
+mod symbols;
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use elf::ObjectInfo;
+
+struct LoadedObject {
+    path: PathBuf,
+    info: ObjectInfo,
+}
+
+impl LoadedObject {
+    fn load(path: &Path) -> Result<Self, String> {
+        let data =
+            std::fs::read(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let info = elf::parse(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_owned(),
+            info,
+        })
+    }
+
+    /// Loads the object at `path`, followed by its dependencies (`DT_NEEDED`) that
+    /// live in the same directory.
+    fn load_with_neighbours(path: &Path) -> Result<Vec<Self>, String> {
+        let object = Self::load(path)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut objects = Vec::new();
+        for needed in &object.info.needed {
+            let path = dir.join(needed);
+            if path.exists() {
+                objects.push(Self::load(&path)?);
+            }
+        }
+        objects.insert(0, object);
+        Ok(objects)
+    }
+
+    fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("Usage: rubicon-check [check] <host> [module]...");
+    eprintln!("       rubicon-check symbols <object>...");
+    eprintln!();
+    eprintln!("check: checks that the modules are compatible with the globals exported by");
+    eprintln!("       the host (or the shared objects it depends on), without loading anything.");
+    eprintln!("symbols: lists the rubicon globals exported and imported by each object.");
+    ExitCode::from(2)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return usage();
+    }
+    match args.first().map(|arg| arg.as_str()) {
+        None | Some("symbols") | Some("check") if args.len() < 2 => return usage(),
+        _ => {}
+    }
+
+    let result = match args[0].as_str() {
+        "symbols" => symbols::run(&args[1..]).map(|()| true),
+        "check" => check::run(&args[1..]),
+        _ => check::run(&args),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("rubicon-check: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! `rubicon-check symbols <object>...`: lists what each object exports and imports.
+//!
+//! Imports can only be resolved against the objects given (and their dependencies):
+//! to see where a module's imports come from, pass the host binary too.
+
+use std::path::Path;
+
+use crate::elf::{COMPATIBILITY_INFO_SUFFIX, EXPECTED_SUFFIX, INFO_SUFFIX};
+use crate::LoadedObject;
+
+const EXPORT_SUFFIX: &str = "__RUBICON_EXPORT";
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    // the objects given, and whatever they depend on that lives next to them,
+    // since that's where imports are typically satisfied from
+    let mut objects: Vec<LoadedObject> = Vec::new();
+    for path in args {
+        for object in LoadedObject::load_with_neighbours(Path::new(path))? {
+            let canonical = object.path.canonicalize().ok();
+            if !objects
+                .iter()
+                .any(|o| o.path.canonicalize().ok() == canonical)
+            {
+                objects.push(object);
+            }
+        }
+    }
+
+    for (i, object) in objects.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", object.path.display());
+        print_exports(object);
+        print_crates(object);
+        print_imports(object, &objects);
+    }
+    Ok(())
+}
+
+fn print_exports(object: &LoadedObject) {
+    let mut exports: Vec<&str> = object
+        .info
+        .exports
+        .iter()
+        .filter_map(|name| name.strip_suffix(EXPORT_SUFFIX))
+        .collect();
+    if exports.is_empty() {
+        return;
+    }
+    exports.sort();
+
+    println!("  exports:");
+    for global in exports {
+        // rubicon's own globals (e.g. the mismatch handler) don't have an info table
+        let Some(info) = object.info.tables.get(&format!("{global}{INFO_SUFFIX}")) else {
+            println!("    {global}{EXPORT_SUFFIX} (rubicon)");
+            continue;
+        };
+        let field = |key: &str| {
+            info.iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+                .unwrap_or("?")
+        };
+        println!(
+            "    {global}{EXPORT_SUFFIX}: {} ({}, crate {})",
+            field("type"),
+            field("kind"),
+            field("crate"),
+        );
+    }
+}
+
+fn print_crates(object: &LoadedObject) {
+    let mut tables: Vec<_> = object
+        .info
+        .tables
+        .iter()
+        .filter_map(|(name, table)| {
+            if let Some(crate_name) = name.strip_suffix(EXPECTED_SUFFIX) {
+                Some((crate_name, "expects", table.as_slice()))
+            } else {
+                let crate_name = name.strip_suffix(COMPATIBILITY_INFO_SUFFIX)?;
+                Some((crate_name, "exports", table.as_slice()))
+            }
+        })
+        .collect();
+    if tables.is_empty() {
+        return;
+    }
+    tables.sort_by_key(|&(crate_name, what, _)| (crate_name, what));
+
+    println!("  crates:");
+    for (crate_name, what, table) in tables {
+        println!("    {crate_name} ({what}):");
+        for (key, value) in table {
+            println!("      {key} = {value}");
+        }
+    }
+}
+
+fn print_imports(object: &LoadedObject, objects: &[LoadedObject]) {
+    let mut imports: Vec<&String> = object.info.imports.iter().collect();
+    if imports.is_empty() {
+        return;
+    }
+    imports.sort();
+
+    // modules aren't linked against the host, so it can't be found from their
+    // dependencies: unless it's given too, there's nothing to say about imports
+    let exporter_given = objects.iter().any(|other| {
+        !std::ptr::eq(other, object)
+            && other
+                .info
+                .exports
+                .iter()
+                .any(|name| name.ends_with(EXPORT_SUFFIX))
+    });
+
+    println!("  imports:");
+    for import in imports {
+        let exporter = objects
+            .iter()
+            .find(|other| !std::ptr::eq(*other, object) && other.info.exports.contains(import));
+        match exporter {
+            Some(exporter) => println!("    {import}: from {}", exporter.name()),
+            None if exporter_given => {
+                println!("    {import}: ❌ not exported by any of these objects")
+            }
+            None => println!("    {import}: unresolved (pass the host binary)"),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Helpers for the build scripts of crates that use [`compatibility_check!`](crate::compatibility_check).
+//!
+//! Listing every cargo feature by hand in `compatibility_check!` is easy to get wrong:
+//! it's easy to forget when a feature is added. Instead, add rubicon as a build
+//! dependency:
+//!
+//! ```toml
+//! [build-dependencies]
+//! rubicon = "3"
+//! ```
+//!
+//! And call [`emit_compatibility_info`] from `build.rs`:
+//!
+//! ```rust,no_run
+//! // in build.rs
+//! rubicon::build::emit_compatibility_info();
+//! ```
+//!
+//! `compatibility_check!` will then include the crate's version, its `opt-level` and
+//! `overflow-checks`, and all its enabled cargo features, on top of whatever keys are
+//! listed in the macro invocation. Crates that don't call it go without these keys:
+//! they're not among the ones `compatibility_check!` always records.
+
+use std::env;
+
+/// The environment variable through which the build script passes the compatibility
+/// info to the crate being compiled. `compatibility_check!` reads it with `option_env!`.
+pub const ENV_VAR: &str = "RUBICON_COMPATIBILITY_INFO";
+
+/// The key under which `compatibility_check!` stores the encoded build info. It's
+/// expanded into individual keys before any comparison.
+#[doc(hidden)]
+pub const COMPATIBILITY_INFO_KEY: &str = "rubicon-build-info";
+
+/// Compatibility info collected from the environment cargo sets for build scripts.
+#[derive(Debug, Clone)]
+pub struct CompatibilityInfo {
+    entries: Vec<(String, String)>,
+}
+
+impl CompatibilityInfo {
+    /// Collects `CARGO_PKG_VERSION`, the crate's `opt-level` and `overflow-checks`, and
+    /// every `CARGO_FEATURE_*` variable.
+    ///
+    /// Cargo only tells build scripts about `opt-level`, so unlike `rustc-version` and
+    /// friends, `opt-level` and `overflow-checks` are not standard keys: they only show
+    /// up for crates whose build script calls this. And cargo doesn't tell anyone about
+    /// `overflow-checks`, so its value is a best guess, which misses `overflow-checks`
+    /// set in a `[profile]` section of `Cargo.toml`.
+    ///
+    /// Cargo uppercases feature names and replaces dashes with underscores, so
+    /// a feature named `rt-multi-thread` ends up as the key `feature:rt-multi-thread`,
+    /// and so does one named `rt_multi_thread`.
+    pub fn from_env() -> Self {
+        let mut entries = Vec::new();
+
+        if let Ok(version) = env::var("CARGO_PKG_VERSION") {
+            entries.push(("pkg-version".to_string(), version));
+        }
+
+        if let Ok(opt_level) = env::var("OPT_LEVEL") {
+            entries.push(("opt-level".to_string(), opt_level));
+        }
+
+        let overflow_checks = overflow_checks()
+            .unwrap_or_else(|| env::var_os("CARGO_CFG_DEBUG_ASSERTIONS").is_some());
+        let overflow_checks = if overflow_checks {
+            "enabled"
+        } else {
+            "disabled"
+        };
+        entries.push(("overflow-checks".to_string(), overflow_checks.to_string()));
+
+        let mut features: Vec<String> = env::vars()
+            .filter_map(|(key, _)| {
+                key.strip_prefix("CARGO_FEATURE_")
+                    .map(|name| name.to_ascii_lowercase().replace('_', "-"))
+            })
+            .collect();
+        features.sort();
+        for feature in features {
+            entries.push((format!("feature:{feature}"), "enabled".to_string()));
+        }
+
+        Self { entries }
+    }
+
+    /// Leaves out a feature that doesn't affect the crate's ABI (e.g. one that only
+    /// adds functions), so that shared objects may disagree on it.
+    pub fn ignore_feature(mut self, name: &str) -> Self {
+        let key = format!("feature:{}", name.to_ascii_lowercase().replace('_', "-"));
+        self.entries.retain(|(k, _)| *k != key);
+        self
+    }
+
+    /// Encodes the info into a single string, suitable for an environment variable.
+    pub fn encode(&self) -> String {
+        let entries: Vec<String> = self
+            .entries
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        entries.join(";")
+    }
+
+    /// Tells cargo to pass the info to the crate being compiled.
+    pub fn emit(&self) {
+        println!("cargo:rustc-env={}={}", ENV_VAR, self.encode());
+    }
+}
+
+/// Whether overflow checks are enabled, as best as can be guessed from a build script.
+///
+/// `CARGO_CFG_OVERFLOW_CHECKS` settles it when the toolchain reports it (it's only set
+/// when they're enabled, so its absence proves nothing). Otherwise, this looks for an
+/// explicit setting in `RUSTFLAGS` or `CARGO_PROFILE_<profile>_OVERFLOW_CHECKS`, and
+/// returns `None` if there's none: rustc then defaults to the value of
+/// `debug-assertions`. `overflow-checks` set in a `[profile]` section of `Cargo.toml`
+/// isn't visible from here, so the guess is wrong for profiles that override it.
+fn overflow_checks() -> Option<bool> {
+    fn parse_bool(value: &str) -> Option<bool> {
+        match value.trim() {
+            "" | "y" | "yes" | "on" | "true" => Some(true),
+            "n" | "no" | "off" | "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    // only set when enabled, by toolchains that report it
+    if env::var_os("CARGO_CFG_OVERFLOW_CHECKS").is_some() {
+        return Some(true);
+    }
+
+    // `-C overflow-checks` in RUSTFLAGS wins over the profile
+    let rustflags = env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+    let mut setting = None;
+    let mut flags = rustflags.split('\x1f');
+    while let Some(flag) = flags.next() {
+        let codegen_opt = match flag {
+            "-C" | "--codegen" => flags.next(),
+            _ => flag
+                .strip_prefix("-C")
+                .or_else(|| flag.strip_prefix("--codegen=")),
+        };
+        if let Some(value) = codegen_opt.and_then(|opt| opt.strip_prefix("overflow-checks")) {
+            setting = parse_bool(value.trim_start_matches('='));
+        }
+    }
+    if setting.is_some() {
+        return setting;
+    }
+
+    // profile settings can be overridden through the environment, e.g.
+    // `CARGO_PROFILE_RELEASE_OVERFLOW_CHECKS=true`
+    let profile = match env::var("PROFILE").ok()?.as_str() {
+        "debug" => "DEV".to_string(),
+        profile => profile.to_uppercase(),
+    };
+    env::var(format!("CARGO_PROFILE_{profile}_OVERFLOW_CHECKS"))
+        .ok()
+        .and_then(|value| parse_bool(&value))
+}
+
+/// Shorthand for `CompatibilityInfo::from_env().emit()`.
+pub fn emit_compatibility_info() {
+    CompatibilityInfo::from_env().emit();
+}
+
+/// Replaces the encoded build info entry (if any) with the individual keys it contains.
+#[doc(hidden)]
+pub fn expand_compatibility_info<'a>(pairs: &[(&'a str, &'a str)]) -> Vec<(&'a str, &'a str)> {
+    let mut expanded = Vec::with_capacity(pairs.len());
+    for &(key, value) in pairs {
+        if key == COMPATIBILITY_INFO_KEY {
+            expanded.extend(value.split(';').filter_map(|entry| entry.split_once('=')));
+        } else {
+            expanded.push((key, value));
+        }
+    }
+    expanded
+}
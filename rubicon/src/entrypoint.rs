@@ -0,0 +1,228 @@
+//! Functions exported by modules with [`entrypoint!`](crate::entrypoint), and called
+//! by the host with their signature checked.
+
+use std::any::type_name;
+
+/// A function pointer type that may be used as the signature of an entrypoint, e.g.
+/// `fn(u32) -> u32` or `extern "C" fn()`.
+///
+/// It's implemented for safe function pointers with the Rust or C ABI, taking up to
+/// 8 arguments.
+pub trait EntrypointSignature: Copy {
+    /// The arguments, as a tuple
+    type Args;
+
+    /// The return type
+    type Output;
+
+    /// Calls the function
+    fn invoke(self, args: Self::Args) -> Self::Output;
+}
+
+macro_rules! impl_entrypoint_signature {
+    ($($arg:ident),*) => {
+        impl<$($arg,)* R> EntrypointSignature for fn($($arg),*) -> R {
+            type Args = ($($arg,)*);
+            type Output = R;
+
+            #[allow(non_snake_case)]
+            fn invoke(self, ($($arg,)*): Self::Args) -> R {
+                self($($arg),*)
+            }
+        }
+
+        impl<$($arg,)* R> EntrypointSignature for extern "C" fn($($arg),*) -> R {
+            type Args = ($($arg,)*);
+            type Output = R;
+
+            #[allow(non_snake_case)]
+            fn invoke(self, ($($arg,)*): Self::Args) -> R {
+                self($($arg),*)
+            }
+        }
+    };
+}
+
+impl_entrypoint_signature!();
+impl_entrypoint_signature!(A);
+impl_entrypoint_signature!(A, B);
+impl_entrypoint_signature!(A, B, C);
+impl_entrypoint_signature!(A, B, C, D);
+impl_entrypoint_signature!(A, B, C, D, E);
+impl_entrypoint_signature!(A, B, C, D, E, F);
+impl_entrypoint_signature!(A, B, C, D, E, F, G);
+impl_entrypoint_signature!(A, B, C, D, E, F, G, H);
+
+/// Hashes the signature of `F`, ABI included (FNV-1a of its type name).
+pub fn signature_hash<F: EntrypointSignature>() -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in type_name::<F>().bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Receives the message of a panic caught on the module's side of an entrypoint.
+#[doc(hidden)]
+#[repr(C)]
+pub struct PanicSink {
+    pub ctx: *mut (),
+    pub write: extern "C" fn(ctx: *mut (), message: *const u8, len: usize),
+}
+
+/// What [`entrypoint!`](crate::entrypoint) exports next to the function: everything
+/// crosses the boundary through the C ABI, so that a host and a module built by different
+/// compilers can at least find out they disagree.
+#[doc(hidden)]
+#[repr(C)]
+pub struct EntrypointDescriptor {
+    signature_hash: extern "C" fn() -> u64,
+    signature: extern "C" fn(len: *mut usize) -> *const u8,
+    call: unsafe extern "C" fn(args: *mut (), ret: *mut (), panic_sink: *mut PanicSink) -> bool,
+}
+
+impl EntrypointDescriptor {
+    pub const fn new<F: EntrypointSignature>(
+        call: unsafe extern "C" fn(args: *mut (), ret: *mut (), panic_sink: *mut PanicSink) -> bool,
+    ) -> Self {
+        extern "C" fn signature_hash_of<F: EntrypointSignature>() -> u64 {
+            signature_hash::<F>()
+        }
+        extern "C" fn signature_of<F: EntrypointSignature>(len: *mut usize) -> *const u8 {
+            let signature = type_name::<F>();
+            unsafe { *len = signature.len() };
+            signature.as_ptr()
+        }
+
+        Self {
+            signature_hash: signature_hash_of::<F>,
+            signature: signature_of::<F>,
+            call,
+        }
+    }
+
+    /// Hash of the signature the module exported the function with
+    pub fn signature_hash(&self) -> u64 {
+        (self.signature_hash)()
+    }
+
+    /// The signature the module exported the function with, e.g. `fn(u32) -> u32`
+    pub fn signature(&self) -> String {
+        let mut len = 0;
+        let ptr = (self.signature)(&mut len);
+        String::from_utf8_lossy(unsafe { std::slice::from_raw_parts(ptr, len) }).into_owned()
+    }
+
+    /// Calls the function, returning the panic message if it panicked.
+    ///
+    /// # Safety
+    ///
+    /// `F` must be the signature the descriptor was created with.
+    pub unsafe fn call<F: EntrypointSignature>(&self, args: F::Args) -> Result<F::Output, String> {
+        extern "C" fn write_message(ctx: *mut (), message: *const u8, len: usize) {
+            let out = unsafe { &mut *(ctx as *mut String) };
+            out.push_str(&String::from_utf8_lossy(unsafe {
+                std::slice::from_raw_parts(message, len)
+            }));
+        }
+
+        let mut args = std::mem::ManuallyDrop::new(args);
+        let mut ret = std::mem::MaybeUninit::<F::Output>::uninit();
+        let mut message = String::new();
+        let mut panic_sink = PanicSink {
+            ctx: &mut message as *mut String as *mut (),
+            write: write_message,
+        };
+
+        // the module takes ownership of the arguments, whether it panics or not
+        let returned = (self.call)(
+            &mut *args as *mut F::Args as *mut (),
+            ret.as_mut_ptr() as *mut (),
+            &mut panic_sink,
+        );
+        if returned {
+            Ok(ret.assume_init())
+        } else {
+            Err(message)
+        }
+    }
+}
+
+/// Called on the module's side by the function generated by [`entrypoint!`](crate::entrypoint).
+///
+/// # Safety
+///
+/// `args` must point to a `F::Args`, which is moved out, `ret` to space for a `F::Output`,
+/// and `panic_sink` to a valid [`PanicSink`].
+#[doc(hidden)]
+pub unsafe fn call_entrypoint<F: EntrypointSignature>(
+    f: F,
+    args: *mut (),
+    ret: *mut (),
+    panic_sink: *mut PanicSink,
+) -> bool {
+    crate::forward_panics();
+    let args = std::ptr::read(args as *mut F::Args);
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f.invoke(args))) {
+        Ok(value) => {
+            std::ptr::write(ret as *mut F::Output, value);
+            true
+        }
+        Err(payload) => {
+            let message = if let Some(message) = payload.downcast_ref::<&str>() {
+                message
+            } else if let Some(message) = payload.downcast_ref::<String>() {
+                message.as_str()
+            } else {
+                "Box<dyn Any>"
+            };
+            let panic_sink = &*panic_sink;
+            (panic_sink.write)(panic_sink.ctx, message.as_ptr(), message.len());
+            false
+        }
+    }
+}
+
+/// Exports a function from a module, along with a hash of its signature (ABI included),
+/// so that the host may call it with `Module::entrypoint`.
+///
+/// ```rust
+/// rubicon::entrypoint! {
+///     pub fn add(a: u32, b: u32) -> u32 {
+///         a + b
+///     }
+/// }
+/// ```
+///
+/// The function itself is left as is. What's exported is a descriptor named after
+/// it (`add__RUBICON_ENTRYPOINT` here), through which the host checks the signature
+/// before calling it, and gets a `Result` back: panics are caught on the module's
+/// side, since they can't unwind into a host that has its own copy of the standard
+/// library. They still reach the host's panic hook, see [`forward_panics`](crate::forward_panics).
+#[macro_export]
+macro_rules! entrypoint {
+    (
+        $(#[$attr:meta])*
+        $vis:vis $(extern $abi:literal)? fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)? $body:block
+    ) => {
+        $(#[$attr])*
+        $vis $(extern $abi)? fn $name($($arg: $ty),*) $(-> $ret)? $body
+
+        const _: () = {
+            type Signature = $(extern $abi)? fn($($ty),*) $(-> $ret)?;
+
+            unsafe extern "C" fn call(
+                args: *mut (),
+                ret: *mut (),
+                panic_sink: *mut $crate::PanicSink,
+            ) -> bool {
+                $crate::call_entrypoint::<Signature>($name, args, ret, panic_sink)
+            }
+
+            #[export_name = concat!(stringify!($name), "__RUBICON_ENTRYPOINT")]
+            static DESCRIPTOR: $crate::EntrypointDescriptor =
+                $crate::EntrypointDescriptor::new::<Signature>(call);
+        };
+    };
+}
@@ -0,0 +1,343 @@
+//! Imported globals resolved with `dlsym` on first access, rather than by the dynamic
+//! loader (the `import-globals-dlsym` feature).
+//!
+//! With link-time imports, a shared object that imports a global nobody exports can't
+//! even be loaded, and the dynamic loader's "undefined symbol" error doesn't say much.
+//! Resolving lazily lets rubicon say which global, crate and module are involved.
+//!
+//! With `import-globals-fallback`, it also lets a module do without an exporter
+//! altogether (e.g. when running its own tests): globals nobody exports are replaced
+//! with a local instance, as if the module had been built without rubicon.
+
+use std::ffi::{c_void, CStr};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::{CompatibilityReport, InfoTable};
+
+/// Looks `symbol` up in every shared object loaded with global visibility (the
+/// executable, and what it links against).
+pub(crate) fn lookup(symbol: &str) -> Option<*mut c_void> {
+    let symbol =
+        CStr::from_bytes_with_nul(symbol.as_bytes()).expect("symbol must be nul-terminated");
+    let address = unsafe { libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr()) };
+    (!address.is_null()).then_some(address)
+}
+
+fn current_module() -> String {
+    crate::report::current_module_path().unwrap_or_else(|| "unknown_so".to_string())
+}
+
+#[cfg_attr(feature = "import-globals-fallback", allow(dead_code))]
+fn missing_export_message(crate_name: &str, global_name: Option<&str>, symbol: &str) -> String {
+    let module = current_module();
+    let symbol = symbol.trim_end_matches('\0');
+    match global_name {
+        Some(global_name) => format!(
+            "rubicon: {module} imports {global_name} from crate {crate_name}, but no loaded shared object exports {symbol} (was the exporter, built with `export-globals`, loaded with global visibility?)"
+        ),
+        None => format!(
+            "rubicon: {module} imports globals from crate {crate_name}, but no loaded shared object exports {symbol} (was the exporter, built with `export-globals`, loaded with global visibility?)"
+        ),
+    }
+}
+
+#[cfg_attr(feature = "import-globals-fallback", allow(dead_code))]
+#[cold]
+fn missing_export(crate_name: &str, global_name: Option<&str>, symbol: &str) -> ! {
+    panic!(
+        "{}",
+        missing_export_message(crate_name, global_name, symbol)
+    )
+}
+
+/// Returns an explanation if nothing exports the crate's compatibility info table
+/// (`symbol`), which the crate's compatibility check can't do without (unless
+/// `import-globals-fallback` is enabled).
+pub(crate) fn missing_compatibility_info(crate_name: &str, symbol: &str) -> Option<String> {
+    if cfg!(feature = "import-globals-fallback") {
+        return None;
+    }
+    let symbol = format!("{symbol}\0");
+    match lookup(&symbol) {
+        Some(_) => None,
+        None => Some(missing_export_message(crate_name, None, &symbol)),
+    }
+}
+
+/// Resolves `symbol` (nul-terminated). If nothing exports it, returns `None` with
+/// `import-globals-fallback`, and panics with an explanation otherwise.
+#[doc(hidden)]
+pub fn import<T>(
+    crate_name: &str,
+    global_name: Option<&str>,
+    symbol: &'static str,
+) -> Option<&'static T> {
+    if let Some(address) = lookup(symbol) {
+        return Some(unsafe { &*(address as *const T) });
+    }
+
+    #[cfg(feature = "import-globals-fallback")]
+    {
+        let _ = (crate_name, global_name);
+        None
+    }
+
+    #[cfg(not(feature = "import-globals-fallback"))]
+    missing_export(crate_name, global_name, symbol)
+}
+
+/// With `import-globals-fallback`, every shared object that imports a `static mut`
+/// process-local defines it too, and relies on the dynamic loader binding it to the
+/// exporter's instance. Panics if it didn't (e.g. on platforms without symbol
+/// interposition), since the shared object would then be on its own.
+#[cfg(feature = "import-globals-fallback")]
+#[doc(hidden)]
+pub fn check_static_mut_binding(
+    crate_name: &str,
+    global_name: &str,
+    symbol: &'static str,
+    ours: *const (),
+) {
+    let Some(exported) = lookup(symbol) else {
+        return;
+    };
+    if exported as *const () != ours {
+        panic!(
+            "rubicon: {} uses its own instance of {global_name} from crate {crate_name}, rather than the one exported as {} (`static mut` process-locals can't fall back on a local instance here: disable `import-globals-fallback`)",
+            current_module(),
+            symbol.trim_end_matches('\0'),
+        );
+    }
+}
+
+/// Resolves the `<crate>_<version>__compatibility_info` table. If nothing exports it,
+/// returns `fallback` (and says so) with `import-globals-fallback`, since there's
+/// nothing to be incompatible with then, and a report with a `missing-export` entry
+/// otherwise.
+#[doc(hidden)]
+pub fn import_compatibility_info(
+    crate_name: &str,
+    symbol: &'static str,
+    fallback: &'static InfoTable,
+) -> Result<&'static InfoTable, CompatibilityReport> {
+    if let Some(address) = lookup(symbol) {
+        return Ok(unsafe { &*(address as *const InfoTable) });
+    }
+    let symbol = symbol.trim_end_matches('\0');
+
+    if cfg!(feature = "import-globals-fallback") {
+        eprintln!(
+            "rubicon: {} imports globals from crate {}, but no loaded shared object exports {}: skipping its compatibility check",
+            current_module(),
+            crate_name,
+            symbol,
+        );
+        return Ok(fallback);
+    }
+
+    let report =
+        CompatibilityReport::from_pairs(crate_name, None, &[], &[("missing-export", symbol)]);
+    Err(report.expect("a key only the importer has never matches"))
+}
+
+/// Like [`TrustedExtern`](crate::TrustedExtern), but the symbol is resolved on first access.
+#[doc(hidden)]
+pub struct LazyExtern<T: 'static> {
+    crate_name: &'static str,
+    global_name: &'static str,
+    symbol: &'static str,
+    /// Set once the symbol is resolved and checked
+    checked: AtomicPtr<T>,
+    check: fn(),
+    /// Local instance used if nothing exports the symbol, with `import-globals-fallback`
+    fallback: Option<&'static T>,
+}
+
+impl<T> LazyExtern<T> {
+    pub const fn new(
+        crate_name: &'static str,
+        global_name: &'static str,
+        symbol: &'static str,
+        check: fn(),
+        fallback: Option<&'static T>,
+    ) -> Self {
+        Self {
+            crate_name,
+            global_name,
+            symbol,
+            checked: AtomicPtr::new(std::ptr::null_mut()),
+            check,
+            fallback,
+        }
+    }
+
+    #[inline(always)]
+    fn get(&self) -> &'static T {
+        let checked = self.checked.load(Ordering::Acquire);
+        if checked.is_null() {
+            return self.first_access();
+        }
+        unsafe { &*checked }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn first_access(&self) -> &'static T {
+        let target = crate::first_access(&self.checked, self.check, || self.resolve());
+        crate::trace::first_access(
+            self.crate_name,
+            self.global_name,
+            target as *const T as *const c_void,
+        );
+        target
+    }
+
+    fn resolve(&self) -> &'static T {
+        match import::<T>(self.crate_name, Some(self.global_name), self.symbol) {
+            Some(global) => global,
+            None => {
+                let fallback = self.fallback.expect(
+                    "rubicon: globals declared with import-globals-fallback have a fallback",
+                );
+                eprintln!(
+                    "rubicon: {} imports {} from crate {}, but no loaded shared object exports {}: using a local instance",
+                    current_module(),
+                    self.global_name,
+                    self.crate_name,
+                    self.symbol.trim_end_matches('\0'),
+                );
+                fallback
+            }
+        }
+    }
+}
+
+impl<T> Deref for LazyExtern<T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        self.get()
+    }
+}
+
+/// Like [`TrustedExternDouble`](crate::TrustedExternDouble), but the symbol is resolved
+/// on first access.
+#[doc(hidden)]
+pub struct LazyExternDouble<T: 'static> {
+    inner: LazyExtern<&'static T>,
+    checked: AtomicPtr<T>,
+}
+
+impl<T> LazyExternDouble<T> {
+    pub const fn new(
+        crate_name: &'static str,
+        global_name: &'static str,
+        symbol: &'static str,
+        check: fn(),
+        fallback: Option<&'static &'static T>,
+    ) -> Self {
+        Self {
+            inner: LazyExtern::new(crate_name, global_name, symbol, check, fallback),
+            checked: AtomicPtr::new(std::ptr::null_mut()),
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn first_access(&self) -> &'static T {
+        // resolving and checking is up to the inner `LazyExtern`
+        let target = *self.inner.get();
+        self.checked
+            .store(target as *const T as *mut T, Ordering::Release);
+        target
+    }
+}
+
+impl<T> Deref for LazyExternDouble<T> {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        let checked = self.checked.load(Ordering::Acquire);
+        if checked.is_null() {
+            return self.first_access();
+        }
+        unsafe { &*checked }
+    }
+}
+
+/// Like [`LazyExtern`], for functions declared with [`process_fn!`](crate::process_fn):
+/// the symbol is the function itself, so `F` is a function pointer type.
+#[doc(hidden)]
+pub struct LazyFn<F: Copy + 'static> {
+    crate_name: &'static str,
+    fn_name: &'static str,
+    symbol: &'static str,
+    /// Set once the symbol is resolved and checked
+    checked: AtomicPtr<c_void>,
+    check: fn(),
+    /// Local copy used if nothing exports the symbol, with `import-globals-fallback`
+    fallback: Option<F>,
+}
+
+impl<F: Copy> LazyFn<F> {
+    pub const fn new(
+        crate_name: &'static str,
+        fn_name: &'static str,
+        symbol: &'static str,
+        check: fn(),
+        fallback: Option<F>,
+    ) -> Self {
+        assert!(std::mem::size_of::<F>() == std::mem::size_of::<*mut c_void>());
+        Self {
+            crate_name,
+            fn_name,
+            symbol,
+            checked: AtomicPtr::new(std::ptr::null_mut()),
+            check,
+            fallback,
+        }
+    }
+
+    #[inline(always)]
+    pub fn get(&self) -> F {
+        let checked = self.checked.load(Ordering::Acquire);
+        if checked.is_null() {
+            return self.first_call();
+        }
+        unsafe { std::mem::transmute_copy::<*mut c_void, F>(&checked) }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn first_call(&self) -> F {
+        // `c_void` stands for the function's code, it's never read through
+        let target = crate::first_access(&self.checked, self.check, || unsafe {
+            &*(self.resolve() as *const c_void)
+        });
+        let address = target as *const c_void;
+        crate::trace::first_access(self.crate_name, self.fn_name, address);
+        unsafe { std::mem::transmute_copy::<*const c_void, F>(&address) }
+    }
+
+    fn resolve(&self) -> *mut c_void {
+        match import::<u8>(self.crate_name, Some(self.fn_name), self.symbol) {
+            Some(function) => function as *const u8 as *mut c_void,
+            None => {
+                let fallback = self.fallback.expect(
+                    "rubicon: functions declared with import-globals-fallback have a fallback",
+                );
+                eprintln!(
+                    "rubicon: {} imports {} from crate {}, but no loaded shared object exports {}: using a local copy",
+                    current_module(),
+                    self.fn_name,
+                    self.crate_name,
+                    self.symbol.trim_end_matches('\0'),
+                );
+                unsafe { std::mem::transmute_copy::<F, *mut c_void>(&fallback) }
+            }
+        }
+    }
+}
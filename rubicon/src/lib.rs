@@ -13,16 +13,51 @@
 //!
 //! - [`thread_local!`]: A drop-in replacement for [`std::thread_local!`]
 //! - [`process_local!`]: Used to declare statics (including `static mut`)
+//! - [`process_fn!`]: Used to declare functions, so that every shared object calls the
+//!   exporter's copy
 //!
 //! These macros behave differently depending on which feature is enabled:
 //!
 //! - `export-globals`: symbols are exported for use by other shared objects
 //! - `import-globals`: symbols are imported from "the dynamic loader namespace"
+//! - `import-globals-dlsym`: like `import-globals`, but symbols are looked up with
+//!   `dlsym` on first access rather than by the dynamic loader, so that a missing
+//!   export is reported by rubicon (naming the global, its crate, and the module)
+//!   instead of making the module fail to load (unix only)
+//! - `import-globals-fallback`: like `import-globals-dlsym`, but globals that nothing
+//!   exports are replaced with a local instance (and a message on stderr), so that
+//!   modules can run on their own, e.g. in their tests
 //! - neither: the macros act as pass-through to standard Rust constructs
 //!
 //! Additionally, the [`compatibility_check!`] macro is provided to help ensure that
 //! common dependencies used by various shared objects are ABI-compatible.
 //!
+//! Every exported global also exports a [`TypeFingerprint`] of its type (name, size,
+//! alignment, whether it needs dropping). Importers compare it against their own idea
+//! of the type on first access, and report a mismatch if they disagree.
+//!
+//! What happens on a mismatch is up to the [`MismatchHandler`] registered with
+//! [`set_mismatch_handler`]: by default, the shared object whose check failed
+//! panics. The `RUBICON_COMPAT` environment variable overrides it at runtime, see
+//! [`CompatPolicy`].
+//!
+//! When a global behaves as if it were duplicated, set `RUBICON_TRACE=1`: on first
+//! access to every imported global, modules print its address, the shared object it
+//! was found in, and whether the compatibility check passed.
+//!
+//! Hosts should load modules with `Module::load` (from the copy of rubicon built with
+//! `export-globals`), which never unloads them, and runs every crate's compatibility
+//! check before returning (see `loaded_modules` for the list of modules loaded so far).
+//!
+//! Hosts that declare their `#[global_allocator]` with `global_allocator!` share it
+//! with modules that use [`SharedAllocator`], so that memory may be freed by another
+//! shared object than the one that allocated it.
+//!
+//! Modules export functions for the host with [`entrypoint!`], which the host looks up
+//! with `Module::entrypoint`: their signature is checked, and panics are caught before
+//! they cross the boundary, once the host's panic hook has seen them (see
+//! [`forward_panics`]).
+//!
 //! ## Explain like I'm five
 //!
 //! Let's assume you're a very precocious five-year old: say you're making a
@@ -85,38 +120,157 @@
 #[cfg(all(feature = "export-globals", feature = "import-globals"))]
 compile_error!("The features `export-globals` and `import-globals` are mutually exclusive, see https://github.com/bearcove/rubicon");
 
+#[cfg(all(feature = "import-globals-dlsym", not(unix)))]
+compile_error!("The `import-globals-dlsym` feature is only supported on unix-like systems");
+
 #[cfg(any(feature = "export-globals", feature = "import-globals"))]
 pub use paste::paste;
 
 #[cfg(feature = "import-globals")]
 pub use libc;
 
+pub mod build;
+
+mod report;
+pub use report::{CompatibilityEntry, CompatibilityReport, RawReport};
+
+mod module;
+#[cfg(feature = "import-globals")]
+#[doc(hidden)]
+pub use module::{
+    check_compatibility_in_constructor, register_compatibility_check, run_compatibility_check,
+};
+#[cfg(all(any(unix, windows), feature = "export-globals"))]
+pub use module::{loaded_modules, Entrypoint, EntrypointError, LoadError, Module};
+#[doc(hidden)]
+pub use module::{CheckOutcome, CompatibilityCheckFn};
+
+#[cfg(feature = "import-globals-dlsym")]
+mod import;
+#[cfg(feature = "import-globals-fallback")]
+#[doc(hidden)]
+pub use import::check_static_mut_binding;
+#[cfg(feature = "import-globals-dlsym")]
+#[doc(hidden)]
+pub use import::{import, import_compatibility_info, LazyExtern, LazyExternDouble, LazyFn};
+
+mod alloc;
+#[doc(hidden)]
+pub use alloc::{set_shared_allocator, AllocatorTable};
+pub use alloc::{DebugAllocator, SharedAllocator};
+
+mod trace;
+
+mod panic;
+pub use panic::{forward_panics, forwarded_panic, panicking, ForwardedPanic};
+
+mod entrypoint;
+#[doc(hidden)]
+pub use entrypoint::{call_entrypoint, EntrypointDescriptor, PanicSink};
+pub use entrypoint::{signature_hash, EntrypointSignature};
+
 #[cfg(any(feature = "export-globals", feature = "import-globals"))]
 pub const RUBICON_RUSTC_VERSION: &str = env!("RUBICON_RUSTC_VERSION");
 
+#[cfg(any(feature = "export-globals", feature = "import-globals"))]
+pub const RUBICON_RUSTC_COMMIT_HASH: &str = env!("RUBICON_RUSTC_COMMIT_HASH");
+
+#[cfg(any(feature = "export-globals", feature = "import-globals"))]
+pub const RUBICON_RUSTC_COMMIT_DATE: &str = env!("RUBICON_RUSTC_COMMIT_DATE");
+
+#[cfg(any(feature = "export-globals", feature = "import-globals"))]
+pub const RUBICON_RUSTC_CHANNEL: &str = env!("RUBICON_RUSTC_CHANNEL");
+
+#[cfg(any(feature = "export-globals", feature = "import-globals"))]
+pub const RUBICON_LLVM_VERSION: &str = env!("RUBICON_LLVM_VERSION");
+
 #[cfg(any(feature = "export-globals", feature = "import-globals"))]
 pub const RUBICON_TARGET_TRIPLE: &str = env!("RUBICON_TARGET_TRIPLE");
 
+#[cfg(any(feature = "export-globals", feature = "import-globals"))]
+pub const RUBICON_TARGET_FEATURES: &str = env!("RUBICON_TARGET_FEATURES");
+
+#[cfg(any(feature = "export-globals", feature = "import-globals"))]
+pub const RUBICON_RUSTFLAGS_HASH: &str = env!("RUBICON_RUSTFLAGS_HASH");
+
 //==============================================================================
 // Wrappers
 //==============================================================================
 
 /// Wrapper around an `extern` `static` ref to avoid requiring `unsafe` for imported globals.
+///
+/// The first access runs the compatibility checks, then remembers the address: from
+/// then on, accessing the global is an acquire load of that address and a null check,
+/// whose branch to the (cold, out of line) first access is never taken again.
 #[doc(hidden)]
-pub struct TrustedExtern<T: 'static>(pub &'static T, pub fn());
+pub struct TrustedExtern<T: 'static> {
+    crate_name: &'static str,
+    global_name: &'static str,
+    target: &'static T,
+    /// The target is a function pointer: the function is what's imported, not the pointer
+    holds_fn: bool,
+    check: fn(),
+    checked: AtomicPtr<T>,
+}
 
 use std::ops::Deref;
 
+impl<T> TrustedExtern<T> {
+    pub const fn new(
+        crate_name: &'static str,
+        global_name: &'static str,
+        target: &'static T,
+        check: fn(),
+    ) -> Self {
+        Self {
+            crate_name,
+            global_name,
+            target,
+            holds_fn: false,
+            check,
+            checked: AtomicPtr::new(std::ptr::null_mut()),
+        }
+    }
+
+    /// For functions declared with `process_fn!`: `target` points to the imported
+    /// function.
+    pub const fn new_fn(
+        crate_name: &'static str,
+        global_name: &'static str,
+        target: &'static T,
+        check: fn(),
+    ) -> Self {
+        assert!(std::mem::size_of::<T>() == std::mem::size_of::<*const std::ffi::c_void>());
+        Self {
+            holds_fn: true,
+            ..Self::new(crate_name, global_name, target, check)
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn first_access(&self) -> &'static T {
+        let target = first_access(&self.checked, self.check, || self.target);
+        let address = if self.holds_fn {
+            unsafe { *(target as *const T as *const *const std::ffi::c_void) }
+        } else {
+            target as *const T as *const std::ffi::c_void
+        };
+        trace::first_access(self.crate_name, self.global_name, address);
+        target
+    }
+}
+
 impl<T> Deref for TrustedExtern<T> {
     type Target = T;
 
     #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        // this is a good time to run compatibility checks
-        #[cfg(not(feature = "no-compatibility-checks-yolo"))]
-        (self.1)();
-
-        self.0
+        let checked = self.checked.load(Ordering::Acquire);
+        if checked.is_null() {
+            return self.first_access();
+        }
+        unsafe { &*checked }
     }
 }
 
@@ -126,23 +280,320 @@ impl<T> Deref for TrustedExtern<T> {
 /// already a ref. Then, in our own static, we can only access the address of that ref, not its
 /// value (since its value is only known as load time, not compile time).
 ///
-/// As a result, imported thread-locals have an additional layer of indirection.
+/// The extra indirection is only paid on first access, which remembers the address of
+/// the thread-local's key, along with running compatibility checks.
 #[doc(hidden)]
-pub struct TrustedExternDouble<T: 'static>(pub &'static &'static T, pub fn());
+pub struct TrustedExternDouble<T: 'static> {
+    crate_name: &'static str,
+    global_name: &'static str,
+    target: &'static &'static T,
+    check: fn(),
+    checked: AtomicPtr<T>,
+}
+
+impl<T> TrustedExternDouble<T> {
+    pub const fn new(
+        crate_name: &'static str,
+        global_name: &'static str,
+        target: &'static &'static T,
+        check: fn(),
+    ) -> Self {
+        Self {
+            crate_name,
+            global_name,
+            target,
+            check,
+            checked: AtomicPtr::new(std::ptr::null_mut()),
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn first_access(&self) -> &'static T {
+        let target = first_access(&self.checked, self.check, || *self.target);
+        // the exported symbol is the ref
+        trace::first_access(
+            self.crate_name,
+            self.global_name,
+            self.target as *const &T as *const std::ffi::c_void,
+        );
+        target
+    }
+}
 
 impl<T> Deref for TrustedExternDouble<T> {
     type Target = T;
 
     #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        // this is a good time to run compatibility checks
-        #[cfg(not(feature = "no-compatibility-checks-yolo"))]
-        (self.1)();
+        let checked = self.checked.load(Ordering::Acquire);
+        if checked.is_null() {
+            return self.first_access();
+        }
+        unsafe { &*checked }
+    }
+}
+
+/// Runs the compatibility checks for a global, then remembers its address, so that
+/// they're off the hot path.
+#[cold]
+#[inline(never)]
+pub(crate) fn first_access<T>(
+    checked: &AtomicPtr<T>,
+    check: fn(),
+    target: impl FnOnce() -> &'static T,
+) -> &'static T {
+    let target = target();
+
+    // this is a good time to run compatibility checks
+    #[cfg(not(feature = "no-compatibility-checks-yolo"))]
+    check();
+    #[cfg(feature = "no-compatibility-checks-yolo")]
+    let _ = check;
+
+    checked.store(target as *const T as *mut T, Ordering::Release);
+    target
+}
+
+//==============================================================================
+// Type fingerprints
+//==============================================================================
+
+/// A summary of a type's identity and layout, exported next to every global so
+/// that importers can make sure they agree with the exporter on what's behind the
+/// symbol.
+///
+/// This catches types that were renamed or reshaped (e.g. a field added behind a
+/// cargo feature) even when nobody thought to list that feature in
+/// [`compatibility_check!`].
+///
+/// It only crosses the boundary through the C ABI, so that an exporter and an
+/// importer built by different compilers can still compare fingerprints.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct TypeFingerprint {
+    // `std::any::type_name` isn't `const` on stable, so we store a function that
+    // returns it and call it at check time instead.
+    type_name: extern "C" fn(len: *mut usize) -> *const u8,
+    size: usize,
+    align: usize,
+    needs_drop: bool,
+}
+
+impl TypeFingerprint {
+    /// Computes the fingerprint of `T`.
+    pub const fn of<T>() -> Self {
+        extern "C" fn type_name_of<T>(len: *mut usize) -> *const u8 {
+            let type_name = std::any::type_name::<T>();
+            unsafe { *len = type_name.len() };
+            type_name.as_ptr()
+        }
+
+        Self {
+            type_name: type_name_of::<T>,
+            size: std::mem::size_of::<T>(),
+            align: std::mem::align_of::<T>(),
+            needs_drop: std::mem::needs_drop::<T>(),
+        }
+    }
+
+    /// The type's name, as returned by [`std::any::type_name`].
+    pub fn type_name(&self) -> &'static str {
+        let mut len = 0;
+        let ptr = (self.type_name)(&mut len);
+        // SAFETY: the name lives in a shared object that's never unloaded
+        let type_name = unsafe { std::slice::from_raw_parts(ptr, len) };
+        std::str::from_utf8(type_name).unwrap_or("<invalid UTF-8>")
+    }
+
+    /// The type's size, as returned by [`std::mem::size_of`].
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The type's alignment, as returned by [`std::mem::align_of`].
+    pub fn align(&self) -> usize {
+        self.align
+    }
+
+    /// Whether the type has drop glue, as returned by [`std::mem::needs_drop`].
+    pub fn needs_drop(&self) -> bool {
+        self.needs_drop
+    }
+}
+
+impl PartialEq for TypeFingerprint {
+    fn eq(&self, other: &Self) -> bool {
+        // don't compare the function pointers: they're from different shared objects
+        self.type_name() == other.type_name()
+            && self.size == other.size
+            && self.align == other.align
+            && self.needs_drop == other.needs_drop
+    }
+}
+
+impl Eq for TypeFingerprint {}
+
+impl std::fmt::Debug for TypeFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypeFingerprint")
+            .field("type_name", &self.type_name())
+            .field("size", &self.size)
+            .field("align", &self.align)
+            .field("needs_drop", &self.needs_drop)
+            .finish()
+    }
+}
+
+/// A table of `(key, value)` string pairs, as exported by [`compatibility_check!`]
+/// (`<crate>_<version>__compatibility_info`) and next to every global
+/// (`<global>__RUBICON_INFO`).
+///
+/// Like [`TypeFingerprint`], it has a C layout: it's read by shared objects built by
+/// other compilers, and by `rubicon-check`, none of which can know how rustc lays out
+/// a `&[(&str, &str)]`. Build one with [`info_table!`].
+#[doc(hidden)]
+#[repr(C)]
+pub struct InfoTable {
+    entries: *const InfoEntry,
+    len: usize,
+}
+
+// SAFETY: only ever points to immutable statics
+unsafe impl Sync for InfoTable {}
+
+#[doc(hidden)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InfoEntry {
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+}
+
+impl InfoEntry {
+    pub const fn from_pairs<const N: usize>(pairs: &[(&'static str, &'static str)]) -> [Self; N] {
+        let mut entries = [InfoEntry {
+            key: std::ptr::null(),
+            key_len: 0,
+            value: std::ptr::null(),
+            value_len: 0,
+        }; N];
+        let mut i = 0;
+        while i < N {
+            let (key, value) = pairs[i];
+            entries[i] = InfoEntry {
+                key: key.as_ptr(),
+                key_len: key.len(),
+                value: value.as_ptr(),
+                value_len: value.len(),
+            };
+            i += 1;
+        }
+        entries
+    }
+}
+
+impl InfoTable {
+    pub const fn new(entries: &'static [InfoEntry]) -> Self {
+        Self {
+            entries: entries.as_ptr(),
+            len: entries.len(),
+        }
+    }
+
+    /// The table's pairs, in order.
+    pub fn pairs(&self) -> Vec<(&'static str, &'static str)> {
+        // SAFETY: built by `info_table!` from `&'static str`s, in a shared object
+        // that's never unloaded
+        unsafe fn str_at(ptr: *const u8, len: usize) -> &'static str {
+            std::str::from_utf8(std::slice::from_raw_parts(ptr, len)).unwrap_or("<invalid UTF-8>")
+        }
+
+        let entries = unsafe { std::slice::from_raw_parts(self.entries, self.len) };
+        entries
+            .iter()
+            .map(|entry| unsafe {
+                (
+                    str_at(entry.key, entry.key_len),
+                    str_at(entry.value, entry.value_len),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Builds an [`InfoTable`] in a const context, from `(key, value)` string pairs
+/// (which may have `#[cfg]` attributes).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! info_table {
+    ($($pairs:tt)*) => {{
+        const PAIRS: &[(&str, &str)] = &[$($pairs)*];
+        const ENTRIES: [$crate::InfoEntry; PAIRS.len()] = $crate::InfoEntry::from_pairs(PAIRS);
+        $crate::InfoTable::new(&ENTRIES)
+    }};
+}
+
+/// Compares the fingerprint exported alongside a global with the one computed
+/// by the importer.
+pub fn check_fingerprint(
+    global_name: &str,
+    crate_name: &str,
+    exported: &TypeFingerprint,
+    imported: &TypeFingerprint,
+) -> Result<(), CompatibilityReport> {
+    if exported == imported {
+        return Ok(());
+    }
 
-        self.0
+    fn pairs(fingerprint: &TypeFingerprint) -> [(&'static str, String); 4] {
+        [
+            ("type_name", fingerprint.type_name().to_string()),
+            ("size_of", fingerprint.size.to_string()),
+            ("align_of", fingerprint.align.to_string()),
+            ("needs_drop", fingerprint.needs_drop.to_string()),
+        ]
+    }
+    let exported = pairs(exported);
+    let imported = pairs(imported);
+    let exported: Vec<_> = exported.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    let imported: Vec<_> = imported.iter().map(|(k, v)| (*k, v.as_str())).collect();
+
+    match CompatibilityReport::from_pairs(crate_name, Some(global_name), &exported, &imported) {
+        Some(report) => Err(report),
+        None => Ok(()),
     }
 }
 
+//==============================================================================
+// Symbol names
+//==============================================================================
+
+/// Evaluates to the default symbol name of a global: its crate's name and
+/// semver-compatible version, then its own name. That version is the major version,
+/// or `0_<minor>` for 0.x crates, since cargo considers 0.1 and 0.2 incompatible.
+#[cfg(any(feature = "export-globals", feature = "import-globals"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! default_symbol {
+    ($name:ident) => {
+        ::std::concat!(::std::env!("CARGO_PKG_NAME"), "_", $crate::default_symbol!(@version), "__", ::std::stringify!($name))
+    };
+
+    // `env!` can't be matched on, but an identifier pasted from it can
+    (@version) => {
+        $crate::paste! { $crate::default_symbol!(@version [<v env!("CARGO_PKG_VERSION_MAJOR")>]) }
+    };
+    (@version v0) => {
+        ::std::concat!("0_", ::std::env!("CARGO_PKG_VERSION_MINOR"))
+    };
+    (@version $major:ident) => {
+        ::std::env!("CARGO_PKG_VERSION_MAJOR")
+    };
+}
+
 //==============================================================================
 // Thread-locals
 //==============================================================================
@@ -175,19 +626,42 @@ impl<T> Deref for TrustedExternDouble<T> {
 /// forwards to [`std::thread_local`], resulting in no performance penalty,
 /// no difference in binary size, etc.
 ///
-/// ## Name mangling, collisions
+/// ## Symbol names
 ///
-/// When the `import-globals` or `export-globals` feature is enabled, name mangling
-/// will be disabled for thread-locals declared through this macro (due to unfortunate
-/// limitations of the Rust attributes used to implement this).
+/// When the `import-globals` or `export-globals` feature is enabled, thread-locals
+/// declared through this macro aren't mangled the way Rust items normally are. Instead,
+/// they're exported under the crate's name and major version, followed by their own
+/// name: `FOO` in version 1.2.3 of crate `my-crate` is exported as `my-crate_1__FOO`
+/// (plus rubicon's suffixes), so it won't collide with another crate's `FOO`. For
+/// 0.x versions, the minor version counts too: `FOO` in version 0.2.3 is exported as
+/// `my-crate_0_2__FOO`.
 ///
-/// We recommend prefixing your thread-locals with your crate/module name to
-/// avoid collisions:
+/// Crates that need a name that stays put across versions and refactors can pick
+/// their own with `#[rubicon(name = "...")]`, in which case it's up to them to avoid
+/// collisions:
 ///
 /// ```rust
 /// # use std::sync::atomic::AtomicU32;
 /// rubicon::thread_local! {
-///     static MY_CRATE_FOO: AtomicU32 = AtomicU32::new(42);
+///     #[rubicon(name = "my_crate_FOO")]
+///     static FOO: AtomicU32 = AtomicU32::new(42);
+/// }
+/// ```
+///
+/// ## `const` initializers
+///
+/// Thread-locals initialized with a `const { ... }` block stay that way when
+/// exported, so that accessing them skips the lazy initialization check, just like
+/// with [`std::thread_local`]:
+///
+/// ```rust
+/// # use std::cell::Cell;
+/// rubicon::thread_local! {
+///     static DEPTH: Cell<u32> = const { Cell::new(0) };
+///     static LIMIT: Cell<u32> = const {
+///         let limit = 128;
+///         Cell::new(limit)
+///     };
 /// }
 /// ```
 ///
@@ -206,9 +680,28 @@ impl<T> Deref for TrustedExternDouble<T> {
 #[cfg(not(any(feature = "import-globals", feature = "export-globals")))]
 #[macro_export]
 macro_rules! thread_local {
+    // empty (base case for the recursion)
+    () => {};
+
+    // rubicon's own attributes mean nothing to std: strip them
+    (@attrs [$($attrs:tt)*] #[rubicon($($args:tt)*)] $($rest:tt)*) => {
+        $crate::thread_local!(@attrs [$($attrs)*] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] #[$attr:meta] $($rest:tt)*) => {
+        $crate::thread_local!(@attrs [$($attrs)* #[$attr]] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] $vis:vis static $name:ident: $ty:ty = const $init:block $(; $($rest:tt)*)?) => {
+        ::std::thread_local! { $($attrs)* $vis static $name: $ty = const $init; }
+        $($crate::thread_local!($($rest)*);)?
+    };
+    (@attrs [$($attrs:tt)*] $vis:vis static $name:ident: $ty:ty = $init:expr $(; $($rest:tt)*)?) => {
+        ::std::thread_local! { $($attrs)* $vis static $name: $ty = $init; }
+        $($crate::thread_local!($($rest)*);)?
+    };
+
     ($($tts:tt)+) => {
-        ::std::thread_local!{ $($tts)+ }
-    }
+        $crate::thread_local!(@attrs [] $($tts)+);
+    };
 }
 
 #[cfg(any(feature = "export-globals", feature = "import-globals"))]
@@ -217,67 +710,235 @@ macro_rules! thread_local {
     // empty (base case for the recursion)
     () => {};
 
-    ($(#[$attrs:meta])* $vis:vis static $name:ident: $ty:ty = const { $expr:expr } $(;)?) => {
-        $crate::thread_local! {
-            $(#[$attrs])*
-            $vis static $name: $ty = $expr;
-        }
+    // sort rubicon's own attributes from the others
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] #[rubicon(name = $name_override:tt)] $($rest:tt)*) => {
+        $crate::thread_local!(@attrs [$($attrs)*] [$name_override] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] #[$attr:meta] $($rest:tt)*) => {
+        $crate::thread_local!(@attrs [$($attrs)* #[$attr]] [$($symbol)*] $($rest)*);
+    };
+
+    // by default, globals are exported under their crate's name and version, then
+    // their own name
+    (@attrs [$($attrs:tt)*] [] $vis:vis static $name:ident: $($rest:tt)*) => {
+        $crate::thread_local!(@attrs [$($attrs)*] [$crate::default_symbol!($name)] $vis static $name: $($rest)*);
+    };
+
+    // the initializer is forwarded as-is, so that const-initialized thread-locals
+    // keep std's fast path
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] $vis:vis static $name:ident: $ty:ty = const $init:block $(; $($rest:tt)*)?) => {
+        $crate::thread_local_inner!($($attrs)* $vis $name, $ty, [const $init], [$($symbol)*]);
+        $($crate::thread_local!($($rest)*);)?
     };
 
-    ($(#[$attrs:meta])* $vis:vis static $name:ident: $ty:ty = $expr:expr $(;)?) => {
-        $crate::thread_local_inner!($(#[$attrs])* $vis $name, $ty, $expr);
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] $vis:vis static $name:ident: $ty:ty = $expr:expr $(; $($rest:tt)*)?) => {
+        $crate::thread_local_inner!($($attrs)* $vis $name, $ty, [$expr], [$($symbol)*]);
+        $($crate::thread_local!($($rest)*);)?
     };
 
-    // handle multiple declarations
-    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => (
-        $crate::thread_local_inner!($(#[$attr])* $vis $name, $t, $init);
-        $crate::thread_local!($($rest)*);
-    );
+    ($($tts:tt)+) => {
+        $crate::thread_local!(@attrs [] [] $($tts)+);
+    };
 }
 
 #[cfg(feature = "export-globals")]
 #[macro_export]
 macro_rules! thread_local_inner {
-    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr) => {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, [$($init:tt)*], [$($symbol:tt)*]) => {
         $crate::paste! {
             // regular thread-local macro, not exported.
             ::std::thread_local! {
                 $(#[$attrs])*
-                $vis static $name: $ty = $expr;
+                $vis static $name: $ty = $($init)*;
             }
 
-            #[no_mangle]
+            #[export_name = concat!($($symbol)*, "__RUBICON_EXPORT")]
             #[allow(clippy::non_upper_case_globals)]
             static [<$name __RUBICON_EXPORT>]: &::std::thread::LocalKey<$ty> = &$name;
+
+            #[export_name = concat!($($symbol)*, "__RUBICON_FINGERPRINT")]
+            #[allow(clippy::non_upper_case_globals)]
+            static [<$name __RUBICON_FINGERPRINT>]: $crate::TypeFingerprint = $crate::TypeFingerprint::of::<$ty>();
+
+            // read by `rubicon-check symbols`
+            #[export_name = concat!($($symbol)*, "__RUBICON_INFO")]
+            #[allow(clippy::non_upper_case_globals)]
+            static [<$name __RUBICON_INFO>]: $crate::InfoTable = $crate::info_table![
+                ("crate", env!("CARGO_PKG_NAME")),
+                ("kind", "thread-local"),
+                ("type", stringify!($ty)),
+            ];
         }
     };
 }
 
-#[cfg(feature = "import-globals")]
+#[cfg(all(feature = "import-globals", not(feature = "import-globals-dlsym")))]
 #[macro_export]
 #[allow(clippy::crate_in_macro_def)] // we _do_ mean the invocation site's crate, not the macro's
 macro_rules! thread_local_inner {
-    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr) => {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, [$($init:tt)*], [$($symbol:tt)*]) => {
         $crate::paste! {
             extern "Rust" {
-                #[link_name = stringify!([<$name __RUBICON_EXPORT>])]
+                #[link_name = concat!($($symbol)*, "__RUBICON_EXPORT")]
                 #[allow(improper_ctypes)]
                 #[allow(clippy::non_upper_case_globals)]
                 static [<$name __RUBICON_IMPORT>]: &'static ::std::thread::LocalKey<$ty>;
+
+                #[link_name = concat!($($symbol)*, "__RUBICON_FINGERPRINT")]
+                #[allow(clippy::non_upper_case_globals)]
+                static [<$name __RUBICON_FINGERPRINT_IMPORT>]: $crate::TypeFingerprint;
+            }
+
+            #[allow(non_snake_case)]
+            fn [<$name __RUBICON_CHECK>]() {
+                crate::compatibility_check_once();
+
+                static FINGERPRINT_CHECK_ONCE: ::std::sync::Once = ::std::sync::Once::new();
+                FINGERPRINT_CHECK_ONCE.call_once(|| {
+                    if let Err(report) = $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), unsafe { &[<$name __RUBICON_FINGERPRINT_IMPORT>] }, &$crate::TypeFingerprint::of::<$ty>()) {
+                        $crate::report_mismatch(&report);
+                    }
+                });
             }
 
             // even though this ends up being not a LocalKey, but a type that Derefs to LocalKey,
             // in practice, most codebases work just fine with this, since they call methods
             // that takes `self: &LocalKey`: they don't see the difference.
-            $vis static $name: $crate::TrustedExternDouble<::std::thread::LocalKey<$ty>> = $crate::TrustedExternDouble(unsafe { &[<$name __RUBICON_IMPORT>] }, crate::compatibility_check_once);
+            $vis static $name: $crate::TrustedExternDouble<::std::thread::LocalKey<$ty>> = $crate::TrustedExternDouble::new(env!("CARGO_PKG_NAME"), stringify!($name), unsafe { &[<$name __RUBICON_IMPORT>] }, [<$name __RUBICON_CHECK>]);
+        }
+    };
+}
+
+#[cfg(feature = "import-globals-dlsym")]
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)] // we _do_ mean the invocation site's crate, not the macro's
+macro_rules! thread_local_inner {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, [$($init:tt)*], [$($symbol:tt)*]) => {
+        $crate::paste! {
+            #[allow(non_snake_case)]
+            fn [<$name __RUBICON_CHECK>]() {
+                crate::compatibility_check_once();
+
+                static FINGERPRINT_CHECK_ONCE: ::std::sync::Once = ::std::sync::Once::new();
+                FINGERPRINT_CHECK_ONCE.call_once(|| {
+                    // nothing to compare with if we fell back to a local instance
+                    let Some(exported) = $crate::import::<$crate::TypeFingerprint>(env!("CARGO_PKG_NAME"), Some(stringify!($name)), concat!($($symbol)*, "__RUBICON_FINGERPRINT\0")) else {
+                        return;
+                    };
+                    if let Err(report) = $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), exported, &$crate::TypeFingerprint::of::<$ty>()) {
+                        $crate::report_mismatch(&report);
+                    }
+                });
+            }
+
+            $vis static $name: $crate::LazyExternDouble<::std::thread::LocalKey<$ty>> = $crate::LazyExternDouble::new(env!("CARGO_PKG_NAME"), stringify!($name), concat!($($symbol)*, "__RUBICON_EXPORT\0"), [<$name __RUBICON_CHECK>], $crate::import_fallback!(thread_local, $ty, $($init)*));
         }
     };
 }
 
+/// Evaluates to the local instance an imported global falls back to when nothing
+/// exports it, with `import-globals-fallback`, and to `None` otherwise.
+#[cfg(all(
+    feature = "import-globals-dlsym",
+    not(feature = "import-globals-fallback")
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! import_fallback {
+    ($kind:ident, $ty:ty, $($init:tt)*) => {
+        None
+    };
+}
+
+#[cfg(feature = "import-globals-fallback")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! import_fallback {
+    (thread_local, $ty:ty, $($init:tt)*) => {
+        Some({
+            ::std::thread_local! {
+                static FALLBACK: $ty = $($init)*;
+            }
+            static FALLBACK_REF: &::std::thread::LocalKey<$ty> = &FALLBACK;
+            &FALLBACK_REF
+        })
+    };
+    (process_local, $ty:ty, $expr:expr) => {
+        Some({
+            static FALLBACK: $ty = $expr;
+            &FALLBACK
+        })
+    };
+    (process_fn, $ty:ty, $local:expr) => {
+        Some($local as $ty)
+    };
+}
+
 //==============================================================================
 // Process-locals (statics)
 //==============================================================================
 
+/// A process-local value that can be mutated without `unsafe`, for use in
+/// [`process_local!`] instead of a `static mut`.
+///
+/// Accesses are serialized with a lock. Unlike [`std::sync::Mutex`], it isn't poisoned
+/// by a panic: modules catch their own panics (see [`entrypoint!`]), and one module's
+/// panic shouldn't make a global unusable for every other module.
+pub struct StaticMut<T> {
+    lock: std::sync::Mutex<()>,
+    value: std::cell::UnsafeCell<T>,
+}
+
+// SAFETY: the value is only ever accessed with the lock held.
+unsafe impl<T: Send> Sync for StaticMut<T> {}
+
+impl<T> StaticMut<T> {
+    /// Creates a `StaticMut` holding `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            lock: std::sync::Mutex::new(()),
+            value: std::cell::UnsafeCell::new(value),
+        }
+    }
+
+    /// Calls `f` with exclusive access to the value.
+    ///
+    /// Calling `with` on the same `StaticMut` from within `f` deadlocks.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        f(unsafe { &mut *self.value.get() })
+    }
+
+    /// Sets the value.
+    pub fn set(&self, value: T) {
+        self.with(|v| *v = value);
+    }
+
+    /// Replaces the value, returning the old one.
+    pub fn replace(&self, value: T) -> T {
+        self.with(|v| std::mem::replace(v, value))
+    }
+
+    /// Returns a raw pointer to the value, for code that synchronizes access to it
+    /// by other means.
+    pub fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+}
+
+impl<T: Copy> StaticMut<T> {
+    /// Returns a copy of the value.
+    pub fn get(&self) -> T {
+        self.with(|v| *v)
+    }
+}
+
+impl<T: Default> Default for StaticMut<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
 /// Imports or exports a `static`, depending on the enabled cargo features.
 ///
 /// Before:
@@ -301,18 +962,16 @@ macro_rules! thread_local_inner {
 /// will expand to the static declaration itself, resulting in no performance penalty,
 /// no difference in binary size, etc.
 ///
-/// ## Name mangling, collisions
-///
-/// When the `import-globals` or `export-globals` feature is enabled, name mangling
-/// will be disabled for process-locals declared through this macro (due to unfortunate
-/// limitations of the Rust attributes used to implement this).
+/// ## Symbol names
 ///
-/// We recommend prefixing your process-locals with your crate/module name to
-/// avoid collisions:
+/// Like [`thread_local!`], process-locals are exported under the crate's name and
+/// version followed by their own name (e.g. `my-crate_1__FOO`), unless
+/// overridden with `#[rubicon(name = "...")]`:
 ///
 /// ```rust
 /// rubicon::process_local! {
-///     static MY_CRATE_FOO: u32 = 42;
+///     #[rubicon(name = "my_crate_FOO")]
+///     static FOO: u32 = 42;
 /// }
 /// ```
 ///
@@ -333,16 +992,54 @@ macro_rules! thread_local_inner {
 /// }
 /// ```
 ///
+/// Imported `static mut`s are accessed as places, so there's no wrapper to run the
+/// compatibility checks on first access: they run when the shared object is loaded
+/// instead, from a constructor (on ELF platforms and macOS). For the same reason, they
+/// can't be looked up with `dlsym`: with `import-globals-fallback`, the importer
+/// defines its own instance, which the dynamic loader binds to the exporter's.
+///
+/// Accessing a `static mut` requires `unsafe`, and some form of synchronization the
+/// compiler knows nothing about. Unless a crate really needs a `static mut` (say,
+/// because it hands out references to it), a [`StaticMut`] does the same job safely:
+///
+/// ```rust
+/// rubicon::process_local! {
+///     static COUNTER: rubicon::StaticMut<u64> = rubicon::StaticMut::new(0);
+/// }
+///
+/// // instead of `unsafe { COUNTER += 1 }`
+/// COUNTER.with(|counter| *counter += 1);
+/// assert_eq!(COUNTER.get(), 1);
+/// ```
+///
 /// If you're curious about the exact macro expansion, ask rust-analyzer to
 /// expand it for you via its [Expand Macro Recursively](https://rust-analyzer.github.io/manual.html#expand-macro-recursively)
 /// functionalityl.
 #[cfg(all(not(feature = "import-globals"), not(feature = "export-globals")))]
 #[macro_export]
 macro_rules! process_local {
-    // pass through
+    // empty (base case for the recursion)
+    () => {};
+
+    // pass through, minus rubicon's own attributes
+    (@attrs [$($attrs:tt)*] #[rubicon($($args:tt)*)] $($rest:tt)*) => {
+        $crate::process_local!(@attrs [$($attrs)*] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] #[$attr:meta] $($rest:tt)*) => {
+        $crate::process_local!(@attrs [$($attrs)* #[$attr]] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] $vis:vis static mut $name:ident: $ty:ty = $expr:expr $(; $($rest:tt)*)?) => {
+        $($attrs)* $vis static mut $name: $ty = $expr;
+        $($crate::process_local!($($rest)*);)?
+    };
+    (@attrs [$($attrs:tt)*] $vis:vis static $name:ident: $ty:ty = $expr:expr $(; $($rest:tt)*)?) => {
+        $($attrs)* $vis static $name: $ty = $expr;
+        $($crate::process_local!($($rest)*);)?
+    };
+
     ($($tts:tt)+) => {
-        $($tts)+
-    }
+        $crate::process_local!(@attrs [] $($tts)+);
+    };
 }
 
 #[cfg(any(feature = "export-globals", feature = "import-globals"))]
@@ -351,38 +1048,60 @@ macro_rules! process_local {
     // empty (base case for the recursion)
     () => {};
 
-    // single declaration
-    ($(#[$attrs:meta])* $vis:vis static $name:ident: $ty:ty = $expr:expr $(;)?) => {
-        $crate::process_local_inner!($(#[$attrs])* $vis $name, $ty, $expr);
+    // sort rubicon's own attributes from the others
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] #[rubicon(name = $name_override:tt)] $($rest:tt)*) => {
+        $crate::process_local!(@attrs [$($attrs)*] [$name_override] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] #[$attr:meta] $($rest:tt)*) => {
+        $crate::process_local!(@attrs [$($attrs)* #[$attr]] [$($symbol)*] $($rest)*);
     };
 
-    // single declaration (mut)
-    ($(#[$attrs:meta])* $vis:vis static mut $name:ident: $ty:ty = $expr:expr $(;)?) => {
-        $crate::process_local_inner_mut!($(#[$attrs])* $vis $name, $ty, $expr);
+    // by default, globals are exported under their crate's name and version, then
+    // their own name
+    (@attrs [$($attrs:tt)*] [] $vis:vis static mut $name:ident: $($rest:tt)*) => {
+        $crate::process_local!(@attrs [$($attrs)*] [$crate::default_symbol!($name)] $vis static mut $name: $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] [] $vis:vis static $name:ident: $($rest:tt)*) => {
+        $crate::process_local!(@attrs [$($attrs)*] [$crate::default_symbol!($name)] $vis static $name: $($rest)*);
     };
 
+    // mut
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] $vis:vis static mut $name:ident: $ty:ty = $expr:expr $(; $($rest:tt)*)?) => {
+        $crate::process_local_inner_mut!($($attrs)* $vis $name, $ty, $expr, [$($symbol)*]);
+        $($crate::process_local!($($rest)*);)?
+    };
 
-    // handle multiple declarations
-    ($(#[$attrs:meta])* $vis:vis static $name:ident: $ty:ty = $expr:expr; $($rest:tt)*) => {
-        $crate::process_local_inner!($(#[$attrs])* $vis $name, $ty, $expr);
-        $crate::process_local!($($rest)*);
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] $vis:vis static $name:ident: $ty:ty = $expr:expr $(; $($rest:tt)*)?) => {
+        $crate::process_local_inner!($($attrs)* $vis $name, $ty, $expr, [$($symbol)*]);
+        $($crate::process_local!($($rest)*);)?
     };
 
-    // handle multiple declarations
-    ($(#[$attrs:meta])* $vis:vis static mut $name:ident: $ty:ty = $expr:expr; $($rest:tt)*) => {
-        $crate::process_local_inner_mut!($(#[$attrs])* $vis $name, $ty, $expr);
-        $crate::process_local!($($rest)*);
-    }
+    ($($tts:tt)+) => {
+        $crate::process_local!(@attrs [] [] $($tts)+);
+    };
 }
 
 #[cfg(feature = "export-globals")]
 #[macro_export]
 macro_rules! process_local_inner {
-    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr) => {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr, [$($symbol:tt)*]) => {
         $crate::paste! {
-            #[export_name = stringify!([<$name __RUBICON_EXPORT>])]
+            #[export_name = concat!($($symbol)*, "__RUBICON_EXPORT")]
             $(#[$attrs])*
             $vis static $name: $ty = $expr;
+
+            #[export_name = concat!($($symbol)*, "__RUBICON_FINGERPRINT")]
+            #[allow(clippy::non_upper_case_globals)]
+            static [<$name __RUBICON_FINGERPRINT>]: $crate::TypeFingerprint = $crate::TypeFingerprint::of::<$ty>();
+
+            // read by `rubicon-check symbols`
+            #[export_name = concat!($($symbol)*, "__RUBICON_INFO")]
+            #[allow(clippy::non_upper_case_globals)]
+            static [<$name __RUBICON_INFO>]: $crate::InfoTable = $crate::info_table![
+                ("crate", env!("CARGO_PKG_NAME")),
+                ("kind", "process-local"),
+                ("type", stringify!($ty)),
+            ];
         }
     };
 }
@@ -390,359 +1109,839 @@ macro_rules! process_local_inner {
 #[cfg(feature = "export-globals")]
 #[macro_export]
 macro_rules! process_local_inner_mut {
-    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr) => {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr, [$($symbol:tt)*]) => {
         $crate::paste! {
-            #[export_name = stringify!([<$name __RUBICON_EXPORT>])]
+            #[export_name = concat!($($symbol)*, "__RUBICON_EXPORT")]
             $(#[$attrs])*
             $vis static mut $name: $ty = $expr;
+
+            #[export_name = concat!($($symbol)*, "__RUBICON_FINGERPRINT")]
+            #[allow(clippy::non_upper_case_globals)]
+            static [<$name __RUBICON_FINGERPRINT>]: $crate::TypeFingerprint = $crate::TypeFingerprint::of::<$ty>();
+
+            // read by `rubicon-check symbols`
+            #[export_name = concat!($($symbol)*, "__RUBICON_INFO")]
+            #[allow(clippy::non_upper_case_globals)]
+            static [<$name __RUBICON_INFO>]: $crate::InfoTable = $crate::info_table![
+                ("crate", env!("CARGO_PKG_NAME")),
+                ("kind", "process-local-mut"),
+                ("type", stringify!($ty)),
+            ];
         }
     };
 }
 
-#[cfg(feature = "import-globals")]
+#[cfg(all(feature = "import-globals", not(feature = "import-globals-dlsym")))]
 #[macro_export]
 #[allow(clippy::crate_in_macro_def)] // we _do_ mean the invocation site's crate, not the macro's
 macro_rules! process_local_inner {
-    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr) => {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr, [$($symbol:tt)*]) => {
         $crate::paste! {
             extern "Rust" {
-                #[link_name = stringify!([<$name __RUBICON_EXPORT>])]
+                #[link_name = concat!($($symbol)*, "__RUBICON_EXPORT")]
                 #[allow(improper_ctypes)]
                 #[allow(clippy::non_upper_case_globals)]
                 static [<$name __RUBICON_IMPORT>]: $ty;
+
+                #[link_name = concat!($($symbol)*, "__RUBICON_FINGERPRINT")]
+                #[allow(clippy::non_upper_case_globals)]
+                static [<$name __RUBICON_FINGERPRINT_IMPORT>]: $crate::TypeFingerprint;
             }
 
-            $vis static $name: $crate::TrustedExtern<$ty> = $crate::TrustedExtern(unsafe { &[<$name __RUBICON_IMPORT>] }, crate::compatibility_check_once);
-        }
-    };
+            #[allow(non_snake_case)]
+            fn [<$name __RUBICON_CHECK>]() {
+                crate::compatibility_check_once();
+
+                static FINGERPRINT_CHECK_ONCE: ::std::sync::Once = ::std::sync::Once::new();
+                FINGERPRINT_CHECK_ONCE.call_once(|| {
+                    if let Err(report) = $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), unsafe { &[<$name __RUBICON_FINGERPRINT_IMPORT>] }, &$crate::TypeFingerprint::of::<$ty>()) {
+                        $crate::report_mismatch(&report);
+                    }
+                });
+            }
+
+            $vis static $name: $crate::TrustedExtern<$ty> = $crate::TrustedExtern::new(env!("CARGO_PKG_NAME"), stringify!($name), unsafe { &[<$name __RUBICON_IMPORT>] }, [<$name __RUBICON_CHECK>]);
+        }
+    };
 }
 
-#[cfg(feature = "import-globals")]
+#[cfg(feature = "import-globals-dlsym")]
 #[macro_export]
+#[allow(clippy::crate_in_macro_def)] // we _do_ mean the invocation site's crate, not the macro's
+macro_rules! process_local_inner {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr, [$($symbol:tt)*]) => {
+        $crate::paste! {
+            #[allow(non_snake_case)]
+            fn [<$name __RUBICON_CHECK>]() {
+                crate::compatibility_check_once();
+
+                static FINGERPRINT_CHECK_ONCE: ::std::sync::Once = ::std::sync::Once::new();
+                FINGERPRINT_CHECK_ONCE.call_once(|| {
+                    // nothing to compare with if we fell back to a local instance
+                    let Some(exported) = $crate::import::<$crate::TypeFingerprint>(env!("CARGO_PKG_NAME"), Some(stringify!($name)), concat!($($symbol)*, "__RUBICON_FINGERPRINT\0")) else {
+                        return;
+                    };
+                    if let Err(report) = $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), exported, &$crate::TypeFingerprint::of::<$ty>()) {
+                        $crate::report_mismatch(&report);
+                    }
+                });
+            }
+
+            $vis static $name: $crate::LazyExtern<$ty> = $crate::LazyExtern::new(env!("CARGO_PKG_NAME"), stringify!($name), concat!($($symbol)*, "__RUBICON_EXPORT\0"), [<$name __RUBICON_CHECK>], $crate::import_fallback!(process_local, $ty, $expr));
+        }
+    };
+}
+
+#[cfg(all(feature = "import-globals", not(feature = "import-globals-fallback")))]
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)] // we _do_ mean the invocation site's crate, not the macro's
 macro_rules! process_local_inner_mut {
-    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr) => {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr, [$($symbol:tt)*]) => {
         $crate::paste! {
             // externs require "unsafe" to access, but so do "static mut", so,
-            // no need to wrap in `TrustedExtern`
+            // no need to wrap in `TrustedExtern`. Users access it as a place, so
+            // it's resolved by the dynamic loader even with `import-globals-dlsym`.
             extern "Rust" {
-                #[link_name = stringify!([<$name __RUBICON_EXPORT>])]
+                #[link_name = concat!($($symbol)*, "__RUBICON_EXPORT")]
                 #[allow(improper_ctypes)]
                 $vis static mut $name: $ty;
+
+                #[link_name = concat!($($symbol)*, "__RUBICON_FINGERPRINT")]
+                #[allow(clippy::non_upper_case_globals)]
+                static [<$name __RUBICON_FINGERPRINT_IMPORT>]: $crate::TypeFingerprint;
+            }
+
+            // There's no first access to hook into, since there's no wrapper: the
+            // checks run when the shared object is loaded instead, see
+            // `compatibility_check!`.
+            #[allow(non_snake_case)]
+            fn [<$name __RUBICON_CHECK>]() {
+                crate::compatibility_check_once();
+
+                static FINGERPRINT_CHECK_ONCE: ::std::sync::Once = ::std::sync::Once::new();
+                FINGERPRINT_CHECK_ONCE.call_once(|| {
+                    if let Err(report) = $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), unsafe { &[<$name __RUBICON_FINGERPRINT_IMPORT>] }, &$crate::TypeFingerprint::of::<$ty>()) {
+                        $crate::report_mismatch(&report);
+                    }
+                });
             }
+
+            const _: () = {
+                #[used]
+                #[cfg_attr(
+                    any(target_os = "linux", target_os = "android", target_os = "freebsd"),
+                    link_section = ".init_array"
+                )]
+                #[cfg_attr(target_vendor = "apple", link_section = "__DATA,__mod_init_func")]
+                static REGISTER_CHECK: extern "C" fn() = {
+                    extern "C" fn check_on_load() -> $crate::CheckOutcome {
+                        $crate::run_compatibility_check(env!("CARGO_PKG_NAME"), $crate::default_symbol!(compatibility_info), || {
+                            // reports a mismatch once, even if the check is run again
+                            [<$name __RUBICON_CHECK>]();
+                            $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), unsafe { &[<$name __RUBICON_FINGERPRINT_IMPORT>] }, &$crate::TypeFingerprint::of::<$ty>()).is_ok()
+                        })
+                    }
+                    extern "C" fn register_check() {
+                        if !$crate::register_compatibility_check(env!("CARGO_PKG_NAME"), $crate::default_symbol!(compatibility_info), check_on_load) {
+                            $crate::check_compatibility_in_constructor(env!("CARGO_PKG_NAME"), $crate::default_symbol!(compatibility_info), [<$name __RUBICON_CHECK>]);
+                        }
+                    }
+                    register_check
+                };
+            };
         }
     };
 }
 
+#[cfg(feature = "import-globals-fallback")]
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)] // we _do_ mean the invocation site's crate, not the macro's
+macro_rules! process_local_inner_mut {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, $ty:ty, $expr:expr, [$($symbol:tt)*]) => {
+        $crate::paste! {
+            // Users access it as a place, so it can't be resolved with `dlsym`. Instead,
+            // the shared object brings its own instance, under the exporter's symbol:
+            // the dynamic loader binds it to the exporter's instance if there's one
+            // (which the check below makes sure of), and it's used as-is otherwise
+            // (e.g. in the crate's own tests).
+            $(#[$attrs])*
+            #[export_name = concat!($($symbol)*, "__RUBICON_EXPORT")]
+            $vis static mut $name: $ty = $expr;
+
+            #[allow(non_snake_case)]
+            fn [<$name __RUBICON_CHECK>]() {
+                crate::compatibility_check_once();
+
+                static FINGERPRINT_CHECK_ONCE: ::std::sync::Once = ::std::sync::Once::new();
+                FINGERPRINT_CHECK_ONCE.call_once(|| {
+                    // nothing to compare with if we're using our own instance
+                    let Some(exported) = $crate::import::<$crate::TypeFingerprint>(env!("CARGO_PKG_NAME"), Some(stringify!($name)), concat!($($symbol)*, "__RUBICON_FINGERPRINT\0")) else {
+                        return;
+                    };
+                    if let Err(report) = $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), exported, &$crate::TypeFingerprint::of::<$ty>()) {
+                        $crate::report_mismatch(&report);
+                    }
+                    $crate::check_static_mut_binding(env!("CARGO_PKG_NAME"), stringify!($name), concat!($($symbol)*, "__RUBICON_EXPORT\0"), unsafe { ::std::ptr::addr_of!($name) } as *const ());
+                });
+            }
+
+            const _: () = {
+                #[used]
+                #[cfg_attr(
+                    any(target_os = "linux", target_os = "android", target_os = "freebsd"),
+                    link_section = ".init_array"
+                )]
+                #[cfg_attr(target_vendor = "apple", link_section = "__DATA,__mod_init_func")]
+                static REGISTER_CHECK: extern "C" fn() = {
+                    extern "C" fn check_on_load() -> $crate::CheckOutcome {
+                        // reports a mismatch once, even if the check is run again
+                        $crate::run_compatibility_check(env!("CARGO_PKG_NAME"), $crate::default_symbol!(compatibility_info), || {
+                            [<$name __RUBICON_CHECK>]();
+                            match $crate::import::<$crate::TypeFingerprint>(env!("CARGO_PKG_NAME"), Some(stringify!($name)), concat!($($symbol)*, "__RUBICON_FINGERPRINT\0")) {
+                                Some(exported) => $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), exported, &$crate::TypeFingerprint::of::<$ty>()).is_ok(),
+                                None => true,
+                            }
+                        })
+                    }
+                    extern "C" fn register_check() {
+                        if !$crate::register_compatibility_check(env!("CARGO_PKG_NAME"), $crate::default_symbol!(compatibility_info), check_on_load) {
+                            $crate::check_compatibility_in_constructor(env!("CARGO_PKG_NAME"), $crate::default_symbol!(compatibility_info), [<$name __RUBICON_CHECK>]);
+                        }
+                    }
+                    register_check
+                };
+            };
+        }
+    };
+}
+
+/// Declares process-locals that are initialized on first access, like a
+/// [`std::sync::LazyLock`], once for the whole process.
+///
+// importing needs an exporter to link against, and doctests don't have one
+#[cfg_attr(not(feature = "import-globals"), doc = "```rust")]
+#[cfg_attr(feature = "import-globals", doc = "```rust,ignore")]
+/// # use std::collections::HashMap;
+/// # use std::sync::Mutex;
+/// rubicon::lazy_process_local! {
+///     static REGISTRY: Mutex<HashMap<String, u32>> = Mutex::new(HashMap::new());
+/// }
+///
+/// REGISTRY.lock().unwrap().insert("answer".to_string(), 42);
+/// ```
+///
+/// Each global is a [`process_local!`] of type `LazyLock<T>`: the exporter owns both
+/// the value and the state that says whether it's been initialized, along with the
+/// initializer itself. Whichever shared object accesses the global first runs the
+/// exporter's copy of the initializer, and every other shared object waits for it
+/// and then sees the same value.
+///
+/// Method calls go straight to the value. When importing, the global derefs to the
+/// `LazyLock` rather than to the value, so to get a `&T` that works either way, let
+/// deref coercion do it:
+///
+// importing needs an exporter to link against, and doctests don't have one
+#[cfg_attr(not(feature = "import-globals"), doc = "```rust")]
+#[cfg_attr(feature = "import-globals", doc = "```rust,ignore")]
+/// rubicon::lazy_process_local! {
+///     static ANSWER: u64 = 42;
+/// }
+///
+/// let answer: &u64 = &ANSWER;
+/// assert_eq!(*answer, 42);
+/// ```
+///
+/// `#[rubicon(name = "...")]` and multiple declarations are supported, as with
+/// [`process_local!`].
+#[macro_export]
+macro_rules! lazy_process_local {
+    // empty (base case for the recursion)
+    () => {};
+
+    // attributes are forwarded as tokens, so that `process_local!` can still tell
+    // rubicon's own from the others
+    ($(#[$($attr:tt)*])* $vis:vis static $name:ident: $ty:ty = $expr:expr $(; $($rest:tt)*)?) => {
+        $crate::process_local! {
+            $(#[$($attr)*])*
+            $vis static $name: ::std::sync::LazyLock<$ty> = ::std::sync::LazyLock::new(|| $expr);
+        }
+        $($crate::lazy_process_local!($($rest)*);)?
+    };
+}
+
 //==============================================================================
-// Compatibility check
+// Process functions
 //==============================================================================
 
-#[cfg(feature = "export-globals")]
+/// Imports or exports a function, depending on the enabled cargo features.
+///
+/// Some crates keep their global state behind functions rather than statics, or need
+/// every shared object to go through a single implementation of some hook. With
+/// `export-globals`, functions declared with `process_fn!` are exported; with
+/// `import-globals`, calling one calls the exporter's copy:
+///
+/// ```rust
+/// rubicon::process_fn! {
+///     pub fn next_id() -> u64 {
+///         // only the exporter's copy of this static is ever used
+///         static NEXT_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+///         NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+///     }
+/// }
+///
+/// assert_eq!(next_id(), 0);
+/// ```
+///
+/// Like globals, functions are exported under the crate's name and version
+/// followed by their own name, unless overridden with `#[rubicon(name = "...")]`.
+/// They export a [`TypeFingerprint`] of their signature, which importers check along
+/// with the crate's compatibility info on the first call.
+///
+/// Only plain functions are supported: no generics, no `self`, no `async`, no
+/// `unsafe`, and arguments must be named with identifiers, not patterns.
+///
+/// When neither feature is enabled, the macro expands to the functions themselves.
+#[cfg(all(not(feature = "import-globals"), not(feature = "export-globals")))]
 #[macro_export]
-macro_rules! compatibility_check {
-    ($($feature:tt)*) => {
-        use std::env;
+macro_rules! process_fn {
+    // empty (base case for the recursion)
+    () => {};
 
-        $crate::paste! {
-            #[no_mangle]
-            #[export_name = concat!(env!("CARGO_PKG_NAME"), "_compatibility_info")]
-            static __RUBICON_COMPATIBILITY_INFO_: &'static [(&'static str, &'static str)] = &[
-                ("rustc-version", $crate::RUBICON_RUSTC_VERSION),
-                ("target-triple", $crate::RUBICON_TARGET_TRIPLE),
-                $($feature)*
-            ];
-        }
+    // pass through, minus rubicon's own attributes
+    (@attrs [$($attrs:tt)*] #[rubicon($($args:tt)*)] $($rest:tt)*) => {
+        $crate::process_fn!(@attrs [$($attrs)*] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] #[$attr:meta] $($rest:tt)*) => {
+        $crate::process_fn!(@attrs [$($attrs)* #[$attr]] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] $vis:vis fn $name:ident($($arg:ident: $argty:ty),* $(,)?) $(-> $ret:ty)? $body:block $($rest:tt)*) => {
+        $($attrs)* $vis fn $name($($arg: $argty),*) $(-> $ret)? $body
+        $crate::process_fn!($($rest)*);
+    };
 
-        pub fn compatibility_check_once() {
-            // no-op when exporting
-        }
+    ($($tts:tt)+) => {
+        $crate::process_fn!(@attrs [] $($tts)+);
     };
 }
 
-#[cfg(all(unix, feature = "import-globals"))]
+#[cfg(any(feature = "export-globals", feature = "import-globals"))]
 #[macro_export]
-macro_rules! compatibility_check {
-    ($($feature:tt)*) => {
-        use std::env;
+macro_rules! process_fn {
+    // empty (base case for the recursion)
+    () => {};
 
-        extern "Rust" {
-            #[link_name = concat!(env!("CARGO_PKG_NAME"), "_compatibility_info")]
-            static COMPATIBILITY_INFO: &'static [(&'static str, &'static str)];
-        }
+    // sort rubicon's own attributes from the others
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] #[rubicon(name = $name_override:tt)] $($rest:tt)*) => {
+        $crate::process_fn!(@attrs [$($attrs)*] [$name_override] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] #[$attr:meta] $($rest:tt)*) => {
+        $crate::process_fn!(@attrs [$($attrs)* #[$attr]] [$($symbol)*] $($rest)*);
+    };
+
+    // by default, functions are exported under their crate's name and version, then
+    // their own name
+    (@attrs [$($attrs:tt)*] [] $vis:vis fn $name:ident $($rest:tt)*) => {
+        $crate::process_fn!(@attrs [$($attrs)*] [$crate::default_symbol!($name)] $vis fn $name $($rest)*);
+    };
 
+    (@attrs [$($attrs:tt)*] [$($symbol:tt)*] $vis:vis fn $name:ident($($arg:ident: $argty:ty),* $(,)?) $(-> $ret:ty)? $body:block $($rest:tt)*) => {
+        $crate::process_fn_inner!($($attrs)* $vis $name, ($($arg: $argty),*), ($($ret)?), $body, [$($symbol)*]);
+        $crate::process_fn!($($rest)*);
+    };
 
-        fn get_shared_object_name() -> Option<String> {
-            use $crate::libc::{c_void, Dl_info};
-            use std::ffi::CStr;
-            use std::ptr;
+    ($($tts:tt)+) => {
+        $crate::process_fn!(@attrs [] [] $($tts)+);
+    };
+}
 
-            extern "C" {
-                fn dladdr(addr: *const c_void, info: *mut Dl_info) -> i32;
-            }
+#[cfg(feature = "export-globals")]
+#[macro_export]
+macro_rules! process_fn_inner {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, ($($arg:ident: $argty:ty),*), ($($ret:ty)?), $body:block, [$($symbol:tt)*]) => {
+        $crate::paste! {
+            #[export_name = concat!($($symbol)*, "__RUBICON_EXPORT")]
+            $(#[$attrs])*
+            $vis fn $name($($arg: $argty),*) $(-> $ret)? $body
 
-            unsafe {
-                let mut info: Dl_info = std::mem::zeroed();
-                if dladdr(get_shared_object_name as *const c_void, &mut info) != 0 {
-                    let c_str = CStr::from_ptr(info.dli_fname);
-                    return Some(c_str.to_string_lossy().into_owned());
-                }
-            }
-            None
+            #[export_name = concat!($($symbol)*, "__RUBICON_FINGERPRINT")]
+            #[allow(clippy::non_upper_case_globals)]
+            static [<$name __RUBICON_FINGERPRINT>]: $crate::TypeFingerprint = $crate::TypeFingerprint::of::<fn($($argty),*) $(-> $ret)?>();
+
+            // read by `rubicon-check symbols`
+            #[export_name = concat!($($symbol)*, "__RUBICON_INFO")]
+            #[allow(clippy::non_upper_case_globals)]
+            static [<$name __RUBICON_INFO>]: $crate::InfoTable = $crate::info_table![
+                ("crate", env!("CARGO_PKG_NAME")),
+                ("kind", "process-fn"),
+                ("type", stringify!(fn($($argty),*) $(-> $ret)?)),
+            ];
         }
+    };
+}
 
-        struct AnsiEscape<D: std::fmt::Display>(u64, D);
+#[cfg(all(feature = "import-globals", not(feature = "import-globals-dlsym")))]
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)] // we _do_ mean the invocation site's crate, not the macro's
+macro_rules! process_fn_inner {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, ($($arg:ident: $argty:ty),*), ($($ret:ty)?), $body:block, [$($symbol:tt)*]) => {
+        $crate::paste! {
+            extern "Rust" {
+                #[link_name = concat!($($symbol)*, "__RUBICON_EXPORT")]
+                fn [<$name __RUBICON_IMPORT>]($($arg: $argty),*) $(-> $ret)?;
 
-        impl<D: std::fmt::Display> std::fmt::Display for AnsiEscape<D> {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                let inner = format!("\x1b[{}m{}\x1b[0m", self.0, self.1);
-                f.pad(&inner)
+                #[link_name = concat!($($symbol)*, "__RUBICON_FINGERPRINT")]
+                #[allow(clippy::non_upper_case_globals)]
+                static [<$name __RUBICON_FINGERPRINT_IMPORT>]: $crate::TypeFingerprint;
             }
-        }
 
-        #[derive(Clone, Copy)]
-        struct AnsiColor(u64);
+            #[allow(non_snake_case)]
+            fn [<$name __RUBICON_CHECK>]() {
+                crate::compatibility_check_once();
 
-        impl AnsiColor {
-            const BLUE: AnsiColor = AnsiColor(34);
-            const GREEN: AnsiColor = AnsiColor(32);
-            const RED: AnsiColor = AnsiColor(31);
-            const GREY: AnsiColor = AnsiColor(37);
-        }
+                static FINGERPRINT_CHECK_ONCE: ::std::sync::Once = ::std::sync::Once::new();
+                FINGERPRINT_CHECK_ONCE.call_once(|| {
+                    if let Err(report) = $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), unsafe { &[<$name __RUBICON_FINGERPRINT_IMPORT>] }, &$crate::TypeFingerprint::of::<fn($($argty),*) $(-> $ret)?>()) {
+                        $crate::report_mismatch(&report);
+                    }
+                });
+            }
 
-        fn colored<D: std::fmt::Display>(color: AnsiColor, d: D) -> AnsiEscape<D> {
-            AnsiEscape(color.0, d)
-        }
-        fn blue<D: std::fmt::Display>(d: D) -> AnsiEscape<D> {
-            AnsiEscape(34, d)
-        }
-        fn green<D: std::fmt::Display>(d: D) -> AnsiEscape<D> {
-            AnsiEscape(32, d)
-        }
-        fn red<D: std::fmt::Display>(d: D) -> AnsiEscape<D> {
-            AnsiEscape(31, d)
-        }
-        fn grey<D: std::fmt::Display>(d: D) -> AnsiEscape<D> {
-            AnsiEscape(35, d)
-        }
+            #[allow(non_upper_case_globals)]
+            static [<$name __RUBICON_FN>]: unsafe fn($($argty),*) $(-> $ret)? = [<$name __RUBICON_IMPORT>];
+            #[allow(non_upper_case_globals)]
+            static [<$name __RUBICON_TRUSTED>]: $crate::TrustedExtern<unsafe fn($($argty),*) $(-> $ret)?> = $crate::TrustedExtern::new_fn(env!("CARGO_PKG_NAME"), stringify!($name), &[<$name __RUBICON_FN>], [<$name __RUBICON_CHECK>]);
 
-        // Helper function to count visible characters (ignoring ANSI escapes)
-        fn visible_len(s: &str) -> usize {
-            let mut len = 0;
-            let mut in_escape = false;
-            for c in s.chars() {
-                if c == '\x1b' {
-                    in_escape = true;
-                } else if in_escape {
-                    if c.is_alphabetic() {
-                        in_escape = false;
-                    }
-                } else {
-                    len += 1;
-                }
+            $(#[$attrs])*
+            $vis fn $name($($arg: $argty),*) $(-> $ret)? {
+                unsafe { (*[<$name __RUBICON_TRUSTED>])($($arg),*) }
             }
-            len
         }
+    };
+}
 
-        pub fn compatibility_check_once() {
-            fn check_compatibility() {
-                let imported: &[(&str, &str)] = &[
-                    ("rustc-version", $crate::RUBICON_RUSTC_VERSION),
-                    ("target-triple", $crate::RUBICON_TARGET_TRIPLE),
-                    $($feature)*
-                ];
-                let exported = unsafe { COMPATIBILITY_INFO };
-
-                let missing: Vec<_> = imported.iter().filter(|&item| !exported.contains(item)).collect();
-                let extra: Vec<_> = exported.iter().filter(|&item| !imported.contains(item)).collect();
-
-                if missing.is_empty() && extra.is_empty() {
-                    // all good
-                    return;
-                }
+#[cfg(feature = "import-globals-dlsym")]
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)] // we _do_ mean the invocation site's crate, not the macro's
+macro_rules! process_fn_inner {
+    ($(#[$attrs:meta])* $vis:vis $name:ident, ($($arg:ident: $argty:ty),*), ($($ret:ty)?), $body:block, [$($symbol:tt)*]) => {
+        $crate::paste! {
+            #[allow(non_snake_case)]
+            fn [<$name __RUBICON_CHECK>]() {
+                crate::compatibility_check_once();
+
+                static FINGERPRINT_CHECK_ONCE: ::std::sync::Once = ::std::sync::Once::new();
+                FINGERPRINT_CHECK_ONCE.call_once(|| {
+                    // nothing to compare with if we fell back to a local copy
+                    let Some(exported) = $crate::import::<$crate::TypeFingerprint>(env!("CARGO_PKG_NAME"), Some(stringify!($name)), concat!($($symbol)*, "__RUBICON_FINGERPRINT\0")) else {
+                        return;
+                    };
+                    if let Err(report) = $crate::check_fingerprint(stringify!($name), env!("CARGO_PKG_NAME"), exported, &$crate::TypeFingerprint::of::<fn($($argty),*) $(-> $ret)?>()) {
+                        $crate::report_mismatch(&report);
+                    }
+                });
+            }
 
-                let so_name = get_shared_object_name().unwrap_or("unknown_so".to_string());
-                // get only the last bit of the path
-                let so_name = so_name.rsplit('/').next().unwrap_or("unknown_so");
+            // only called with `import-globals-fallback`, if nothing exports the function
+            #[allow(dead_code, non_snake_case)]
+            fn [<$name __RUBICON_LOCAL>]($($arg: $argty),*) $(-> $ret)? $body
 
-                let exe_name = std::env::current_exe().map(|p| p.file_name().unwrap().to_string_lossy().to_string()).unwrap_or_else(|_| "unknown_exe".to_string());
+            #[allow(non_upper_case_globals)]
+            static [<$name __RUBICON_LAZY>]: $crate::LazyFn<fn($($argty),*) $(-> $ret)?> = $crate::LazyFn::new(env!("CARGO_PKG_NAME"), stringify!($name), concat!($($symbol)*, "__RUBICON_EXPORT\0"), [<$name __RUBICON_CHECK>], $crate::import_fallback!(process_fn, fn($($argty),*) $(-> $ret)?, [<$name __RUBICON_LOCAL>]));
 
-                let mut error_message = String::new();
-                error_message.push_str("\n\x1b[31m━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━\x1b[0m\n");
-                error_message.push_str(&format!(" 💀 Feature mismatch for crate \x1b[31m{}\x1b[0m\n\n", env!("CARGO_PKG_NAME")));
+            $(#[$attrs])*
+            $vis fn $name($($arg: $argty),*) $(-> $ret)? {
+                ([<$name __RUBICON_LAZY>].get())($($arg),*)
+            }
+        }
+    };
+}
 
-                error_message.push_str(&format!("{} has an incompatible configuration for {}.\n\n", blue(so_name), red(env!("CARGO_PKG_NAME"))));
+//==============================================================================
+// Mismatch handling
+//==============================================================================
 
-                // Compute max lengths for alignment
-                let max_exported_len = exported.iter().map(|(k, v)| format!("{}={}", k, v).len()).max().unwrap_or(0);
-                let max_ref_len = imported.iter().map(|(k, v)| format!("{}={}", k, v).len()).max().unwrap_or(0);
-                let column_width = max_exported_len.max(max_ref_len);
+/// Decides what happens when a compatibility check fails, see [`set_mismatch_handler`].
+///
+/// The handler may well live in another shared object than the check that failed,
+/// with its own copy of the standard library, or even built by another compiler. So
+/// it's called through the C ABI, with a [`RawReport`] that's only valid for the
+/// duration of the call. And since a panic can't unwind from one copy of std to the
+/// other, handlers don't panic: they return a [`MismatchAction`], and the shared
+/// object whose check failed acts on it.
+pub type MismatchHandler = extern "C" fn(report: *const RawReport) -> MismatchAction;
+
+/// What a [`MismatchHandler`] wants done about a failed compatibility check.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MismatchAction {
+    /// Panic with the rendered report, from the shared object whose check failed.
+    /// `Module::load` catches the panic and returns `LoadError::Incompatible`.
+    Panic,
+
+    /// Keep going as if the check had passed
+    Continue,
+}
 
-                // Gather all unique keys
-                let mut all_keys: Vec<&str> = Vec::new();
-                for (key, _) in exported.iter() {
-                    if !all_keys.contains(key) {
-                        all_keys.push(key);
-                    }
-                }
-                for (key, _) in imported.iter() {
-                    if !all_keys.contains(key) {
-                        all_keys.push(key);
-                    }
-                }
+use std::sync::atomic::{AtomicPtr, Ordering};
 
-                struct Grid {
-                    rows: Vec<Vec<String>>,
-                    column_widths: Vec<usize>,
-                }
+// The handler is owned by the exporter, so that there's only one for the whole
+// process, no matter which shared object a check fails in. A null pointer means
+// "use the default", ie. `panic_on_mismatch`.
+#[cfg(feature = "export-globals")]
+#[export_name = "RUBICON_MISMATCH_HANDLER__RUBICON_EXPORT"]
+static RUBICON_MISMATCH_HANDLER: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
 
-                impl Grid {
-                    fn new() -> Self {
-                        Grid {
-                            rows: Vec::new(),
-                            column_widths: Vec::new(),
-                        }
-                    }
+#[cfg(all(feature = "import-globals", not(feature = "import-globals-dlsym")))]
+extern "Rust" {
+    #[link_name = "RUBICON_MISMATCH_HANDLER__RUBICON_EXPORT"]
+    static RUBICON_MISMATCH_HANDLER: AtomicPtr<()>;
+}
 
-                    fn add_row(&mut self, row: Vec<String>) {
-                        if self.column_widths.len() < row.len() {
-                            self.column_widths.resize(row.len(), 0);
-                        }
-                        for (i, cell) in row.iter().enumerate() {
-                            self.column_widths[i] = self.column_widths[i].max(visible_len(cell));
-                        }
-                        self.rows.push(row);
-                    }
+#[cfg(not(any(feature = "export-globals", feature = "import-globals")))]
+static RUBICON_MISMATCH_HANDLER: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
 
-                    fn write_to(&self, out: &mut String) {
-                        let total_width: usize = self.column_widths.iter().sum::<usize>() + self.column_widths.len() * 3 - 1;
+#[cfg(not(feature = "import-globals-dlsym"))]
+fn mismatch_handler_slot() -> &'static AtomicPtr<()> {
+    #[allow(unused_unsafe)]
+    unsafe {
+        &RUBICON_MISMATCH_HANDLER
+    }
+}
 
-                        // Top border
-                        out.push_str(&format!("┌{}┐\n", "─".repeat(total_width)));
+// Without an exporter, there's nothing to share the handler with: keep our own.
+#[cfg(feature = "import-globals-dlsym")]
+fn mismatch_handler_slot() -> &'static AtomicPtr<()> {
+    static LOCAL: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
+    static SLOT: std::sync::OnceLock<&'static AtomicPtr<()>> = std::sync::OnceLock::new();
+    SLOT.get_or_init(
+        || match import::lookup("RUBICON_MISMATCH_HANDLER__RUBICON_EXPORT\0") {
+            Some(address) => unsafe { &*(address as *const AtomicPtr<()>) },
+            None => &LOCAL,
+        },
+    )
+}
 
-                        for (i, row) in self.rows.iter().enumerate() {
-                            if i == 1 {
-                                // Separator after header
-                                out.push_str(&format!("╞{}╡\n", "═".repeat(total_width)));
-                            }
+/// Registers the function called whenever a compatibility check fails (be it a
+/// [`compatibility_check!`] or a [`TypeFingerprint`] mismatch), for the whole process.
+///
+/// The handler is stored by the shared object that exports globals, so it only needs
+/// to be registered once, typically by the host before loading any modules:
+///
+// importing needs an exporter to link against, and doctests don't have one
+#[cfg_attr(not(feature = "import-globals"), doc = "```rust")]
+#[cfg_attr(feature = "import-globals", doc = "```rust,ignore")]
+/// extern "C" fn crash_report(report: *const rubicon::RawReport) -> rubicon::MismatchAction {
+///     let report = unsafe { &*report };
+///     std::fs::write("rubicon-crash.txt", report.rendered()).ok();
+///     rubicon::MismatchAction::Panic
+/// }
+///
+/// rubicon::set_mismatch_handler(crash_report);
+/// # rubicon::set_mismatch_handler(rubicon::panic_on_mismatch);
+/// ```
+///
+/// rubicon comes with [`panic_on_mismatch`] (the default), [`abort_on_mismatch`]
+/// and [`warn_on_mismatch`].
+///
+/// Handlers must not panic themselves: return [`MismatchAction::Panic`] instead. A
+/// panic can't unwind out of an `extern "C"` function, so it aborts the process.
+pub fn set_mismatch_handler(handler: MismatchHandler) {
+    mismatch_handler_slot().store(handler as *mut (), Ordering::Release);
+}
 
-                            for (j, cell) in row.iter().enumerate() {
-                                out.push_str("│ ");
-                                out.push_str(cell);
-                                out.push_str(&" ".repeat(self.column_widths[j] - visible_len(cell)));
-                                out.push_str(" ");
-                            }
-                            out.push_str("│\n");
-                        }
+/// Returns the handler registered with [`set_mismatch_handler`], or [`panic_on_mismatch`]
+/// if none was registered.
+pub fn mismatch_handler() -> MismatchHandler {
+    let handler = mismatch_handler_slot().load(Ordering::Acquire);
+    if handler.is_null() {
+        panic_on_mismatch
+    } else {
+        // SAFETY: only ever set from a `MismatchHandler` in `set_mismatch_handler`
+        unsafe { std::mem::transmute::<*mut (), MismatchHandler>(handler) }
+    }
+}
 
-                        // Bottom border
-                        out.push_str(&format!("└{}┘\n", "─".repeat(total_width)));
-                    }
-                }
+/// What to do on a mismatch, as chosen at runtime through the `RUBICON_COMPAT`
+/// environment variable.
+///
+/// When set, it takes precedence over the handler registered with
+/// [`set_mismatch_handler`], so that checks can be hardened or downgraded
+/// without rebuilding anything:
+///
+///   * `RUBICON_COMPAT=panic`: [`panic_on_mismatch`]
+///   * `RUBICON_COMPAT=abort`: [`abort_on_mismatch`]
+///   * `RUBICON_COMPAT=warn`: [`warn_on_mismatch`]
+///   * `RUBICON_COMPAT=off`: mismatches are silently ignored
+///
+/// Unlike the `no-compatibility-checks-yolo` cargo feature, this doesn't remove
+/// the checks, it only changes what happens when one fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompatPolicy {
+    Panic,
+    Abort,
+    Warn,
+    Off,
+}
 
-                let mut grid = Grid::new();
-
-                // Add header
-                grid.add_row(vec!["Key".to_string(), format!("Binary {}", blue(&exe_name)), format!("Module {}", blue(so_name))]);
-
-                for key in all_keys.iter() {
-                    let exported_value = exported.iter().find(|&(k, _)| k == key).map(|(_, v)| v);
-                    let imported_value = imported.iter().find(|&(k, _)| k == key).map(|(_, v)| v);
-
-                    let key_column = colored(AnsiColor::GREY, key).to_string();
-                    let binary_column = format_column(exported_value.as_deref().copied(), imported_value.as_deref().copied(), AnsiColor::GREEN);
-                    let module_column = format_column(imported_value.as_deref().copied(), exported_value.as_deref().copied(), AnsiColor::RED);
-
-                    fn format_column(primary: Option<&str>, secondary: Option<&str>, highlight_color: AnsiColor) -> String {
-                        match primary {
-                            Some(value) => {
-                                if secondary.map_or(false, |v| v == value) {
-                                    colored(AnsiColor::GREY, value).to_string()
-                                } else {
-                                    colored(highlight_color, value).to_string()
-                                }
-                            },
-                            None => colored(AnsiColor::RED, "∅").to_string(),
-                        }
-                    }
+impl CompatPolicy {
+    /// The name of the environment variable [`CompatPolicy::from_env`] reads.
+    pub const ENV_VAR: &'static str = "RUBICON_COMPAT";
+
+    /// Returns the policy set through `RUBICON_COMPAT`, if any.
+    ///
+    /// The variable is only read once per shared object: later changes to the
+    /// environment are not taken into account.
+    pub fn from_env() -> Option<Self> {
+        static POLICY: std::sync::OnceLock<Option<CompatPolicy>> = std::sync::OnceLock::new();
+        *POLICY.get_or_init(|| {
+            let value = std::env::var(Self::ENV_VAR).ok()?;
+            let policy = value.parse().ok();
+            if policy.is_none() {
+                eprintln!(
+                    "rubicon: ignoring invalid {}={:?} (expected panic, abort, warn or off)",
+                    Self::ENV_VAR,
+                    value
+                );
+            }
+            policy
+        })
+    }
+}
 
-                    grid.add_row(vec![key_column, binary_column, module_column]);
-                }
+impl std::str::FromStr for CompatPolicy {
+    type Err = ();
 
-                grid.write_to(&mut error_message);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "panic" => Ok(Self::Panic),
+            "abort" => Ok(Self::Abort),
+            "warn" => Ok(Self::Warn),
+            "off" => Ok(Self::Off),
+            _ => Err(()),
+        }
+    }
+}
 
-                struct MessageBox {
-                    lines: Vec<String>,
-                    max_width: usize,
-                }
+/// Called by the macros when a compatibility check fails.
+#[doc(hidden)]
+pub fn report_mismatch(report: &CompatibilityReport) {
+    trace::record_mismatch(report);
+    let handler = match CompatPolicy::from_env() {
+        Some(CompatPolicy::Panic) => panic_on_mismatch,
+        Some(CompatPolicy::Abort) => abort_on_mismatch,
+        Some(CompatPolicy::Warn) => warn_on_mismatch,
+        Some(CompatPolicy::Off) => return,
+        None => mismatch_handler(),
+    };
+    let action = report.with_raw(|raw| handler(raw));
+    // raised here, so that it's this shared object's copy of std that unwinds
+    if action == MismatchAction::Panic {
+        panic!("{}", report);
+    }
+}
 
-                impl MessageBox {
-                    fn new() -> Self {
-                        MessageBox {
-                            lines: Vec::new(),
-                            max_width: 0,
-                        }
-                    }
+/// Has the shared object whose check failed panic with the rendered report. This is
+/// the default [`MismatchHandler`].
+pub extern "C" fn panic_on_mismatch(_report: *const RawReport) -> MismatchAction {
+    MismatchAction::Panic
+}
 
-                    fn add_line(&mut self, line: String) {
-                        self.max_width = self.max_width.max(visible_len(&line));
-                        self.lines.push(line);
-                    }
+/// Prints the rendered report to stderr, then aborts the process.
+#[allow(clippy::not_unsafe_ptr_arg_deref)] // rubicon only calls handlers with a valid report
+pub extern "C" fn abort_on_mismatch(report: *const RawReport) -> MismatchAction {
+    eprintln!("{}", unsafe { &*report }.rendered());
+    eprintln!("rubicon: incompatible shared object, aborting");
+    std::process::abort();
+}
 
-                    fn add_empty_line(&mut self) {
-                        self.lines.push(String::new());
-                    }
+/// Prints the rendered report to stderr, and keeps going.
+///
+/// Only use this if you know the mismatch is harmless: if it isn't, the process
+/// is going to corrupt memory.
+#[allow(clippy::not_unsafe_ptr_arg_deref)] // rubicon only calls handlers with a valid report
+pub extern "C" fn warn_on_mismatch(report: *const RawReport) -> MismatchAction {
+    eprintln!("{}", unsafe { &*report }.rendered());
+    eprintln!("rubicon: incompatible shared object, continuing anyway");
+    MismatchAction::Continue
+}
 
-                    fn write_to(&self, out: &mut String) {
-                        let box_width = self.max_width + 4;
+//==============================================================================
+// Compatibility check
+//==============================================================================
 
-                        out.push_str("\n");
-                        out.push_str(&format!("┌{}┐\n", "─".repeat(box_width - 2)));
+/// Compares the compatibility info exported alongside a crate's globals with
+/// the one expected by the current shared object.
+///
+/// This is what the `check_compatibility` function generated by [`compatibility_check!`]
+/// calls: you probably want to call that one instead.
+pub fn check_compatibility(
+    crate_name: &str,
+    exported: &[(&str, &str)],
+    imported: &[(&str, &str)],
+) -> Result<(), CompatibilityReport> {
+    let exported = build::expand_compatibility_info(exported);
+    let imported = build::expand_compatibility_info(imported);
+
+    match CompatibilityReport::from_pairs(crate_name, None, &exported, &imported) {
+        Some(report) => Err(report),
+        None => Ok(()),
+    }
+}
 
-                        for line in &self.lines {
-                            if line.is_empty() {
-                                out.push_str(&format!("│{}│\n", " ".repeat(box_width - 2)));
-                            } else {
-                                let visible_line_len = visible_len(line);
-                                let padding = " ".repeat(box_width - 4 - visible_line_len);
-                                out.push_str(&format!("│ {}{} │\n", line, padding));
-                            }
-                        }
+#[cfg(feature = "export-globals")]
+#[macro_export]
+macro_rules! compatibility_check {
+    ($($feature:tt)*) => {
+        use std::env;
 
-                        out.push_str(&format!("└{}┘", "─".repeat(box_width - 2)));
-                    }
-                }
+        $crate::paste! {
+            #[no_mangle]
+            #[export_name = $crate::default_symbol!(compatibility_info)]
+            static __RUBICON_COMPATIBILITY_INFO_: $crate::InfoTable = $crate::info_table![
+                ("rustc-version", $crate::RUBICON_RUSTC_VERSION),
+                ("rustc-commit-hash", $crate::RUBICON_RUSTC_COMMIT_HASH),
+                ("rustc-commit-date", $crate::RUBICON_RUSTC_COMMIT_DATE),
+                ("rustc-channel", $crate::RUBICON_RUSTC_CHANNEL),
+                ("llvm-version", $crate::RUBICON_LLVM_VERSION),
+                ("target-triple", $crate::RUBICON_TARGET_TRIPLE),
+                // evaluated here, so that they describe the crate rather than rubicon
+                ("panic-strategy", if cfg!(panic = "abort") { "abort" } else { "unwind" }),
+                ("debug-assertions", if cfg!(debug_assertions) { "enabled" } else { "disabled" }),
+                ("target-features", $crate::RUBICON_TARGET_FEATURES),
+                ("rustflags-hash", $crate::RUBICON_RUSTFLAGS_HASH),
+                ($crate::build::COMPATIBILITY_INFO_KEY, match option_env!("RUBICON_COMPATIBILITY_INFO") { Some(info) => info, None => "" }),
+                $($feature)*
+            ];
+        }
 
-                error_message.push_str("\nDifferent feature sets may result in different struct layouts, which\n");
-                error_message.push_str("would lead to memory corruption. Instead, we're going to panic now.\n\n");
+        pub fn check_compatibility() -> Result<(), $crate::CompatibilityReport> {
+            // always compatible with ourselves
+            Ok(())
+        }
 
-                error_message.push_str("More info: \x1b[4m\x1b[34mhttps://crates.io/crates/rubicon\x1b[0m\n");
+        pub fn compatibility_check_once() {
+            // no-op when exporting
+        }
+    };
+}
 
-                let mut message_box = MessageBox::new();
-                message_box.add_line(format!("To fix this issue, {} needs to enable", blue(so_name)));
-                message_box.add_line(format!("the same cargo features as {} for crate {}.", blue(&exe_name), red(env!("CARGO_PKG_NAME"))));
-                message_box.add_empty_line();
-                message_box.add_line("\x1b[34mHINT:\x1b[0m".to_string());
-                message_box.add_line(format!("Run `cargo tree -i {} -e features` from both.", red(env!("CARGO_PKG_NAME"))));
+#[cfg(all(unix, feature = "import-globals"))]
+#[macro_export]
+macro_rules! compatibility_check {
+    ($($feature:tt)*) => {
+        use std::env;
 
-                message_box.write_to(&mut error_message);
-                error_message.push_str("\n\x1b[31m━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━\x1b[0m\n");
+        // Exported too, so that `rubicon-check` can find it without running anything
+        #[export_name = $crate::default_symbol!(compatibility_info_expected)]
+        static __RUBICON_COMPATIBILITY_INFO_EXPECTED_: $crate::InfoTable = $crate::info_table![
+            ("rustc-version", $crate::RUBICON_RUSTC_VERSION),
+            ("rustc-commit-hash", $crate::RUBICON_RUSTC_COMMIT_HASH),
+            ("rustc-commit-date", $crate::RUBICON_RUSTC_COMMIT_DATE),
+            ("rustc-channel", $crate::RUBICON_RUSTC_CHANNEL),
+            ("llvm-version", $crate::RUBICON_LLVM_VERSION),
+            ("target-triple", $crate::RUBICON_TARGET_TRIPLE),
+            // evaluated here, so that they describe the crate rather than rubicon
+            ("panic-strategy", if cfg!(panic = "abort") { "abort" } else { "unwind" }),
+            ("debug-assertions", if cfg!(debug_assertions) { "enabled" } else { "disabled" }),
+            ("target-features", $crate::RUBICON_TARGET_FEATURES),
+            ("rustflags-hash", $crate::RUBICON_RUSTFLAGS_HASH),
+            ($crate::build::COMPATIBILITY_INFO_KEY, match option_env!("RUBICON_COMPATIBILITY_INFO") { Some(info) => info, None => "" }),
+            $($feature)*
+        ];
+
+        pub fn check_compatibility() -> Result<(), $crate::CompatibilityReport> {
+            let imported = &__RUBICON_COMPATIBILITY_INFO_EXPECTED_;
+            let exported = $crate::compatibility_info_import!(imported)?;
+
+            $crate::check_compatibility(env!("CARGO_PKG_NAME"), &exported.pairs(), &imported.pairs())
+        }
 
-                panic!("{}", error_message);
+        // Runs the check as soon as the shared object is loaded, rather than on first
+        // access to one of its globals: from `rubicon::Module::load` if that's what's
+        // loading it, right here otherwise.
+        #[used]
+        #[cfg_attr(
+            any(target_os = "linux", target_os = "android", target_os = "freebsd"),
+            link_section = ".init_array"
+        )]
+        #[cfg_attr(target_vendor = "apple", link_section = "__DATA,__mod_init_func")]
+        static __RUBICON_REGISTER_COMPATIBILITY_CHECK: extern "C" fn() = {
+            extern "C" fn check_on_load() -> $crate::CheckOutcome {
+                $crate::run_compatibility_check(env!("CARGO_PKG_NAME"), $crate::default_symbol!(compatibility_info), || {
+                    compatibility_check_once();
+                    __RUBICON_COMPATIBLE.load(::std::sync::atomic::Ordering::Acquire)
+                })
+            }
+            extern "C" fn register_compatibility_check() {
+                if !$crate::register_compatibility_check(env!("CARGO_PKG_NAME"), $crate::default_symbol!(compatibility_info), check_on_load) {
+                    $crate::check_compatibility_in_constructor(env!("CARGO_PKG_NAME"), $crate::default_symbol!(compatibility_info), compatibility_check_once);
+                }
             }
+            register_compatibility_check
+        };
+
+        // The outcome of `compatibility_check_once`, so that running the check again
+        // (e.g. from `Module::load`, after a constructor did) doesn't redo it, nor log
+        // anything twice.
+        static __RUBICON_COMPATIBLE: ::std::sync::atomic::AtomicBool = ::std::sync::atomic::AtomicBool::new(true);
 
+        pub fn compatibility_check_once() {
             // this one is _actually_ meant to exist once per shared object
             static COMPATIBILITY_CHECK_ONCE: std::sync::Once = std::sync::Once::new();
             COMPATIBILITY_CHECK_ONCE.call_once(|| {
-                check_compatibility();
+                if let Err(report) = check_compatibility() {
+                    __RUBICON_COMPATIBLE.store(false, ::std::sync::atomic::Ordering::Release);
+                    $crate::report_mismatch(&report);
+                }
             });
         }
     };
 }
 
+/// Evaluates to the `<crate>_<version>__compatibility_info` table exported alongside the
+/// invoking crate's globals (or to `$fallback` if there's none, with
+/// `import-globals-fallback`), as a `Result<_, CompatibilityReport>`.
+#[cfg(all(
+    unix,
+    feature = "import-globals",
+    not(feature = "import-globals-dlsym")
+))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! compatibility_info_import {
+    ($fallback:expr) => {{
+        extern "Rust" {
+            #[link_name = $crate::default_symbol!(compatibility_info)]
+            static COMPATIBILITY_INFO: $crate::InfoTable;
+        }
+        Ok::<_, $crate::CompatibilityReport>(unsafe { &COMPATIBILITY_INFO })
+    }};
+}
+
+#[cfg(feature = "import-globals-dlsym")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! compatibility_info_import {
+    ($fallback:expr) => {
+        // with `import-globals-fallback`, a module that nothing exports the crate's
+        // globals to is checked against itself
+        $crate::import_compatibility_info(
+            env!("CARGO_PKG_NAME"),
+            concat!($crate::default_symbol!(compatibility_info), "\0"),
+            $fallback,
+        )
+    };
+}
+
 #[cfg(all(not(unix), feature = "import-globals"))]
 #[macro_export]
 macro_rules! compatibility_check {
     ($($feature:tt)*) => {
+        pub fn check_compatibility() -> Result<(), $crate::CompatibilityReport> {
+            // compatibility checks are only supported on unix-like system
+            Ok(())
+        }
+
         pub fn compatibility_check_once() {
             // compatibility checks are only supported on unix-like system
         }
@@ -755,6 +1954,17 @@ macro_rules! compatibility_check {
 /// It exports information about the crate's version and enabled features, which is then used
 /// by the import macros to ensure compatibility between different shared objects.
 ///
+/// On top of the keys you provide, rubicon always records how the shared object was
+/// built: `rustc-version` (along with `rustc-commit-hash`, `rustc-commit-date`,
+/// `rustc-channel` and `llvm-version`, since two nightlies may share a version number),
+/// `target-triple`, `target-features` and `rustflags-hash` (a hash of the `RUSTFLAGS`
+/// that may affect codegen, ignoring linker flags, lints and the like), along with the
+/// crate's own `panic-strategy` and `debug-assertions`. Mixing, say, a release binary
+/// with a debug module is reported like any other mismatch.
+///
+/// A crate's `opt-level` and `overflow-checks` are only known to its build script: they're
+/// recorded if it calls [`build::emit_compatibility_info`].
+///
 /// # Usage
 ///
 /// At a minimum, you should include the crate's version:
@@ -765,7 +1975,10 @@ macro_rules! compatibility_check {
 /// }
 /// ```
 ///
-/// For crates with feature flags that affect struct layouts, you should include those as well:
+/// For crates with feature flags that affect struct layouts, you should include those as well,
+/// either by calling [`build::emit_compatibility_info`] from the crate's build script (which
+/// includes the crate's version, `opt-level`, `overflow-checks` and every enabled feature
+/// automatically), or by hand:
 ///
 /// ```
 /// rubicon::compatibility_check! {
@@ -823,40 +2036,64 @@ macro_rules! compatibility_check {
 ///
 /// # When does the check happen and what happens if it fails?
 ///
-/// The check happens at runtime, lazily, when a global imported from a rubicon-aware
-/// crate is accessed (behind a [`std::sync::Once`]).
+/// The check happens at runtime, as soon as the shared object is loaded: on ELF
+/// platforms and macOS, the macro registers a constructor (in `.init_array` or
+/// `__mod_init_func`) that runs before any of the shared object's code does. If the
+/// shared object is being loaded by `Module::load`, the constructor leaves the check to
+/// it: if the [`MismatchHandler`] asks for a panic, the shared object panics, catches
+/// it, and `Module::load` returns `LoadError::Incompatible`. Otherwise (the shared
+/// object was linked against, or opened with a plain `dlopen`), the constructor runs
+/// the check itself, and since nothing may unwind out of a constructor, the process is
+/// aborted instead.
+///
+/// Elsewhere, the check happens lazily, when a global imported from a rubicon-aware
+/// crate is first accessed (behind a [`std::sync::Once`]).
 ///
-/// If the check fails, the process will panic with a message like:
+/// If the check fails, the [`MismatchHandler`] registered with [`set_mismatch_handler`]
+/// is called, unless the `RUBICON_COMPAT` environment variable says otherwise (see
+/// [`CompatPolicy`]). By default, the process will panic with a message like:
 ///
 /// ```text
 /// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 ///  💀 Feature mismatch for crate mokio
 ///
-/// libmod_b.dylib has an incompatible configuration for mokio.
+/// libmod_b.so has an incompatible configuration for mokio.
 ///
-/// ┌──────────────────────────────────────────────────────────────────┐
-/// │ Key               │ Binary samplebin     │ Module libmod_b.dylib │
-/// ╞══════════════════════════════════════════════════════════════════╡
-/// │ rustc-version     │ 1.81.0               │ 1.81.0                │
-/// │ target-triple     │ aarch64-apple-darwin │ aarch64-apple-darwin  │
-/// │ mokio_pkg_version │ 0.1.0                │ 0.1.0                 │
-/// │ timer             │ disabled             │ enabled               │
-/// │ timer_is_disabled │ 1                    │ ∅                     │
-/// └──────────────────────────────────────────────────────────────────┘
+/// ┌─────────────────────────────────────────────────────────────────────────────────────────────────────────┐
+/// │ Key               │ Binary samplebin                         │ Module libmod_b.so                       │
+/// ╞═════════════════════════════════════════════════════════════════════════════════════════════════════════╡
+/// │ rustc-version     │ 1.95.0                                   │ 1.95.0                                   │
+/// │ rustc-commit-hash │ 59807616e1fa2540724bfbac14d7976d7e4a3860 │ 59807616e1fa2540724bfbac14d7976d7e4a3860 │
+/// │ rustc-commit-date │ 2026-04-14                               │ 2026-04-14                               │
+/// │ rustc-channel     │ stable                                   │ stable                                   │
+/// │ llvm-version      │ 22.1                                     │ 22.1                                     │
+/// │ target-triple     │ x86_64-unknown-linux-gnu                 │ x86_64-unknown-linux-gnu                 │
+/// │ panic-strategy    │ unwind                                   │ unwind                                   │
+/// │ debug-assertions  │ enabled                                  │ enabled                                  │
+/// │ target-features   │ fxsr,sse,sse2                            │ fxsr,sse,sse2                            │
+/// │ rustflags-hash    │ none                                     │ none                                     │
+/// │ pkg-version       │ 0.1.0                                    │ 0.1.0                                    │
+/// │ opt-level         │ 0                                        │ 0                                        │
+/// │ overflow-checks   │ enabled                                  │ enabled                                  │
+/// │ feature:default   │ enabled                                  │ enabled                                  │
+/// │ timer_is_disabled │ 1                                        │ ∅                                        │
+/// │ feature:timer     │ ∅                                        │ enabled                                  │
+/// └─────────────────────────────────────────────────────────────────────────────────────────────────────────┘
 ///
 /// Different feature sets may result in different struct layouts, which
-/// would lead to memory corruption. Instead, we're going to panic now.
+/// would lead to memory corruption.
 ///
 /// More info: https://crates.io/crates/rubicon
 ///
 /// ┌───────────────────────────────────────────────────────┐
-/// │ To fix this issue, libmod_b.dylib needs to enable     │
+/// │ To fix this issue, libmod_b.so needs to enable        │
 /// │ the same cargo features as samplebin for crate mokio. │
 /// │                                                       │
 /// │ HINT:                                                 │
 /// │ Run `cargo tree -i mokio -e features` from both.      │
 /// └───────────────────────────────────────────────────────┘
 /// ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
+/// ```
 ///
 /// # Running compatibility checks manually
 ///
@@ -886,10 +2123,35 @@ macro_rules! compatibility_check {
 ///     }
 /// }
 /// ```
+///
+/// # Checking without panicking
+///
+/// Besides `compatibility_check_once`, the macro generates a `check_compatibility`
+/// function, which runs the check every time it's called and returns a
+/// [`CompatibilityReport`] instead of panicking. A plugin host can use it to reject
+/// an incompatible module and keep running:
+///
+/// ```rust
+/// rubicon::compatibility_check! {
+///     ("version", env!("CARGO_PKG_VERSION")),
+/// }
+///
+/// if let Err(report) = check_compatibility() {
+///     eprintln!("{} can't be used with this binary:", report.module_name());
+///     for entry in report.mismatches() {
+///         eprintln!("  {}: {:?} vs {:?}", entry.key, entry.exported, entry.imported);
+///     }
+/// }
+/// ```
 #[cfg(not(any(feature = "export-globals", feature = "import-globals")))]
 #[macro_export]
 macro_rules! compatibility_check {
     ($($feature:tt)*) => {
+        pub fn check_compatibility() -> Result<(), $crate::CompatibilityReport> {
+            // no-op unless we're importing/exporting globals
+            Ok(())
+        }
+
         pub fn compatibility_check_once() {
             // no-op unless we're importing/exporting globals
         }
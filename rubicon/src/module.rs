@@ -0,0 +1,801 @@
+//! Loading shared objects ("modules") in a way that upholds rubicon's invariants.
+
+/// A crate's compatibility check, as registered by [`compatibility_check!`](crate::compatibility_check)
+/// when a shared object that imports globals is loaded. Nothing unwinds out of it: if
+/// the mismatch handler asks for a panic, it's raised and caught on the shared object's
+/// side, see [`run_compatibility_check`]. It may be run more than once, but only reports a
+/// mismatch the first time.
+#[doc(hidden)]
+pub type CompatibilityCheckFn = extern "C" fn() -> CheckOutcome;
+
+/// What a [`CompatibilityCheckFn`] found.
+#[doc(hidden)]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckOutcome {
+    Compatible,
+    /// The mismatch handler asked to continue: the shared object may be used anyway
+    Incompatible,
+    /// The mismatch handler asked for a panic: the shared object must not be used
+    Rejected,
+    /// Nothing exports the crate's `<crate>_<version>__compatibility_info` table, so there's
+    /// nothing to check against (with `import-globals-dlsym`)
+    MissingExport,
+}
+
+/// Runs `crate_name`'s compatibility check on behalf of a [`CompatibilityCheckFn`].
+/// `check` returns false if it found a mismatch, after reporting it to the mismatch
+/// handler. `symbol` is the crate's compatibility info table.
+#[cfg(feature = "import-globals")]
+#[doc(hidden)]
+pub fn run_compatibility_check(
+    crate_name: &str,
+    symbol: &str,
+    check: impl FnOnce() -> bool,
+) -> CheckOutcome {
+    #[cfg(feature = "import-globals-dlsym")]
+    if crate::import::missing_compatibility_info(crate_name, symbol).is_some() {
+        return CheckOutcome::MissingExport;
+    }
+    #[cfg(not(feature = "import-globals-dlsym"))]
+    let _ = (crate_name, symbol);
+
+    // `report_mismatch` raises the panic the mismatch handler asks for, from this shared
+    // object's copy of std, so it can be caught here
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(check)) {
+        Ok(true) => CheckOutcome::Compatible,
+        Ok(false) => CheckOutcome::Incompatible,
+        Err(_) => CheckOutcome::Rejected,
+    }
+}
+
+// Shared objects register their checks by calling a function exported by the
+// exporter, rather than by touching its data structures: they may well have been
+// built with another rustc, which is exactly what the checks are here to find out.
+//
+// Returns false if the shared object isn't being loaded by `Module::load`, in which
+// case it's up to the constructor to run the check.
+#[cfg(all(any(unix, windows), feature = "export-globals"))]
+#[export_name = "RUBICON_REGISTER_COMPATIBILITY_CHECK__RUBICON_EXPORT"]
+extern "C" fn register_compatibility_check_export(
+    crate_name: *const u8,
+    crate_name_len: usize,
+    symbol: *const u8,
+    symbol_len: usize,
+    check: CompatibilityCheckFn,
+) -> bool {
+    // SAFETY: both come from `env!` in a shared object that's never unloaded.
+    let (crate_name, symbol) = unsafe {
+        (
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(crate_name, crate_name_len)),
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(symbol, symbol_len)),
+        )
+    };
+    loader::register_pending_check(crate_name, symbol, check)
+}
+
+#[cfg(all(feature = "import-globals", not(feature = "import-globals-dlsym")))]
+extern "C" {
+    #[link_name = "RUBICON_REGISTER_COMPATIBILITY_CHECK__RUBICON_EXPORT"]
+    fn register_compatibility_check_import(
+        crate_name: *const u8,
+        crate_name_len: usize,
+        symbol: *const u8,
+        symbol_len: usize,
+        check: CompatibilityCheckFn,
+    ) -> bool;
+}
+
+/// Called from a constructor of every shared object that imports a crate's globals,
+/// so that `Module::load` can run the crate's compatibility check before returning.
+/// Returns false if the shared object isn't being loaded by `Module::load`.
+#[cfg(all(feature = "import-globals", not(feature = "import-globals-dlsym")))]
+#[doc(hidden)]
+pub fn register_compatibility_check(
+    crate_name: &'static str,
+    symbol: &'static str,
+    check: CompatibilityCheckFn,
+) -> bool {
+    unsafe {
+        register_compatibility_check_import(
+            crate_name.as_ptr(),
+            crate_name.len(),
+            symbol.as_ptr(),
+            symbol.len(),
+            check,
+        )
+    }
+}
+
+/// With `import-globals-dlsym`, there may be no exporter to register with: the
+/// constructor then runs the check itself, like it does when the module is loaded by
+/// other means than `Module::load`.
+#[cfg(feature = "import-globals-dlsym")]
+#[doc(hidden)]
+pub fn register_compatibility_check(
+    crate_name: &'static str,
+    symbol: &'static str,
+    check: CompatibilityCheckFn,
+) -> bool {
+    type RegisterFn =
+        extern "C" fn(*const u8, usize, *const u8, usize, CompatibilityCheckFn) -> bool;
+
+    match crate::import::lookup("RUBICON_REGISTER_COMPATIBILITY_CHECK__RUBICON_EXPORT\0") {
+        Some(register) => {
+            let register =
+                unsafe { std::mem::transmute::<*mut std::ffi::c_void, RegisterFn>(register) };
+            register(
+                crate_name.as_ptr(),
+                crate_name.len(),
+                symbol.as_ptr(),
+                symbol.len(),
+                check,
+            )
+        }
+        None => false,
+    }
+}
+
+/// Runs a crate's compatibility check from the constructor of a shared object that
+/// isn't being loaded by `Module::load` (it's linked against, or loaded with a plain
+/// `dlopen`), so that it happens before any of the shared object's code runs.
+///
+/// Nothing may unwind out of a constructor: if the mismatch handler panics, the
+/// process is aborted instead.
+#[cfg(feature = "import-globals")]
+#[doc(hidden)]
+pub fn check_compatibility_in_constructor(crate_name: &str, symbol: &str, check_once: fn()) {
+    #[cfg(any(
+        feature = "no-compatibility-checks-yolo",
+        not(feature = "import-globals-dlsym")
+    ))]
+    let _ = symbol;
+    #[cfg(feature = "no-compatibility-checks-yolo")]
+    let _ = (crate_name, check_once);
+
+    #[cfg(all(
+        not(feature = "no-compatibility-checks-yolo"),
+        feature = "import-globals-dlsym"
+    ))]
+    if let Some(message) = crate::import::missing_compatibility_info(crate_name, symbol) {
+        eprintln!("{message}: aborting");
+        std::process::abort();
+    }
+
+    #[cfg(not(feature = "no-compatibility-checks-yolo"))]
+    if std::panic::catch_unwind(check_once).is_err() {
+        eprintln!(
+            "rubicon: {} has an incompatible configuration for crate {}, and isn't being loaded by Module::load: aborting",
+            crate::report::current_module_path().unwrap_or_else(|| "unknown_so".to_string()),
+            crate_name,
+        );
+        std::process::abort();
+    }
+}
+
+#[cfg(all(any(unix, windows), feature = "export-globals"))]
+pub use loader::*;
+
+#[cfg(all(any(unix, windows), feature = "export-globals"))]
+mod loader {
+    use std::cell::Cell;
+    use std::ffi::c_void;
+    use std::fmt;
+    use std::marker::PhantomData;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+
+    use super::{CheckOutcome, CompatibilityCheckFn};
+    use crate::{EntrypointDescriptor, EntrypointSignature};
+
+    struct PendingCheck {
+        crate_name: &'static str,
+        // the crate's compatibility info table
+        symbol: &'static str,
+        check: CompatibilityCheckFn,
+    }
+
+    // Checks registered by the constructors of shared objects, waiting to be run
+    // by `Module::load`.
+    static PENDING_CHECKS: Mutex<Vec<PendingCheck>> = Mutex::new(Vec::new());
+
+    // Checks registered by the constructors of shared objects that were opened by
+    // other means (and ran their checks themselves), in case `Module::load` is later
+    // asked for one of them.
+    static CHECKED_BY_CONSTRUCTORS: Mutex<Vec<PendingCheck>> = Mutex::new(Vec::new());
+
+    std::thread_local! {
+        // Set while `Module::load` opens a shared object: constructors run on the
+        // thread that opens it.
+        static LOADING: Cell<bool> = const { Cell::new(false) };
+    }
+
+    pub(super) fn register_pending_check(
+        crate_name: &'static str,
+        symbol: &'static str,
+        check: CompatibilityCheckFn,
+    ) -> bool {
+        let loading = LOADING.get();
+        let checks = if loading {
+            &PENDING_CHECKS
+        } else {
+            &CHECKED_BY_CONSTRUCTORS
+        };
+        checks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(PendingCheck {
+                crate_name,
+                symbol,
+                check,
+            });
+        loading
+    }
+
+    /// Takes the checks registered by the constructors of the shared object behind
+    /// `handle`, if it was opened before `Module::load` got to it.
+    fn checked_by_constructors(handle: *mut c_void) -> Vec<PendingCheck> {
+        let mut checks = CHECKED_BY_CONSTRUCTORS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let (ours, others) = std::mem::take(&mut *checks)
+            .into_iter()
+            .partition(|check| sys::contains(handle, check.check as *const c_void));
+        *checks = others;
+        ours
+    }
+
+    /// A shared object loaded with [`Module::load`].
+    ///
+    /// Only available with `export-globals`: modules register their compatibility
+    /// checks with the exporter, so that's who has to load them.
+    ///
+    /// Modules are never unloaded: there's no way to close one, and `load` only ever
+    /// hands out `&'static Module`s, so that symbols looked up in a module may be kept
+    /// around for as long as needed.
+    pub struct Module {
+        path: PathBuf,
+        handle: *mut c_void,
+        crates: Vec<&'static str>,
+        incompatible_crates: Vec<&'static str>,
+    }
+
+    // SAFETY: the handle is only ever passed to `dlsym` / `GetProcAddress`, which
+    // are thread-safe, and the module is never unloaded.
+    unsafe impl Send for Module {}
+    unsafe impl Sync for Module {}
+
+    // Every module loaded so far, in load order. Only ever locked briefly, so that
+    // checks (and mismatch handlers) may call `loaded_modules`.
+    static MODULES: Mutex<Vec<&'static Module>> = Mutex::new(Vec::new());
+
+    struct RejectedModule {
+        handle: usize,
+        error: LoadError,
+    }
+
+    // Shared objects that failed their checks: they can't be unloaded, so loading them
+    // again must fail the same way. Holding the lock while loading also makes sure the
+    // checks registered by a module's constructors aren't mixed up with those of a
+    // module loaded at the same time from another thread.
+    static REJECTED_MODULES: Mutex<Vec<RejectedModule>> = Mutex::new(Vec::new());
+
+    impl Module {
+        /// Loads the shared object at `path`, with all its symbols resolved eagerly
+        /// (`RTLD_NOW`), and runs the compatibility check of every crate it imports
+        /// globals for before returning.
+        ///
+        /// Failed checks are reported to the [`MismatchHandler`](crate::MismatchHandler),
+        /// like any other mismatch. If the handler asks for a panic (the default), the
+        /// module panics, catches it, and this returns [`LoadError::Incompatible`].
+        /// Otherwise, the module is loaded anyway, see [`Module::incompatible_crates`].
+        ///
+        /// Loading the same shared object twice returns the same module, or the same
+        /// error if it was rejected.
+        ///
+        /// Shared objects that were opened by other means first (e.g. with a plain
+        /// `dlopen`) have already run their checks by then: they're run again, to find
+        /// out which crates they cover, without reporting mismatches a second time.
+        pub fn load(path: impl AsRef<Path>) -> Result<&'static Module, LoadError> {
+            let path = path.as_ref();
+            let mut rejected_modules = REJECTED_MODULES.lock().unwrap_or_else(|e| e.into_inner());
+
+            LOADING.set(true);
+            let handle = sys::open(path);
+            LOADING.set(false);
+            let handle = handle.map_err(|message| LoadError::Open {
+                path: path.to_owned(),
+                message,
+            })?;
+            if let Some(module) = loaded_modules()
+                .into_iter()
+                .find(|module| module.handle == handle)
+            {
+                return Ok(module);
+            }
+            if let Some(rejected) = rejected_modules
+                .iter()
+                .find(|rejected| rejected.handle == handle as usize)
+            {
+                return Err(rejected.error.clone());
+            }
+
+            let mut checks =
+                std::mem::take(&mut *PENDING_CHECKS.lock().unwrap_or_else(|e| e.into_inner()));
+            checks.extend(checked_by_constructors(handle));
+            let mut crates = Vec::with_capacity(checks.len());
+            let mut incompatible_crates = Vec::new();
+            let mut rejected_crates = Vec::new();
+            let mut missing_export = None;
+            for check in checks {
+                // a crate's `static mut`s register checks of their own
+                if !crates.contains(&check.crate_name) {
+                    crates.push(check.crate_name);
+                }
+                if rejected_crates.contains(&check.crate_name) {
+                    continue;
+                }
+                let outcome = (check.check)();
+                if outcome == CheckOutcome::MissingExport {
+                    missing_export.get_or_insert((check.crate_name, check.symbol));
+                    continue;
+                }
+                if outcome != CheckOutcome::Compatible
+                    && !incompatible_crates.contains(&check.crate_name)
+                {
+                    incompatible_crates.push(check.crate_name);
+                }
+                if outcome == CheckOutcome::Rejected && !rejected_crates.contains(&check.crate_name)
+                {
+                    rejected_crates.push(check.crate_name);
+                }
+            }
+            let error = if let Some((crate_name, symbol)) = missing_export {
+                Some(LoadError::MissingExport {
+                    path: path.to_owned(),
+                    crate_name: crate_name.to_string(),
+                    symbol: symbol.to_string(),
+                })
+            } else if !rejected_crates.is_empty() {
+                Some(LoadError::Incompatible {
+                    path: path.to_owned(),
+                    crates: rejected_crates.iter().map(|c| c.to_string()).collect(),
+                })
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                rejected_modules.push(RejectedModule {
+                    handle: handle as usize,
+                    error: error.clone(),
+                });
+                return Err(error);
+            }
+
+            let module: &'static Module = Box::leak(Box::new(Module {
+                path: path.to_owned(),
+                handle,
+                crates,
+                incompatible_crates,
+            }));
+            MODULES
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(module);
+            Ok(module)
+        }
+
+        /// The path this module was loaded from, as passed to [`Module::load`].
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        /// The crates this module imports globals for, ie. whose compatibility
+        /// was checked when loading it.
+        pub fn crates(&self) -> &[&'static str] {
+            &self.crates
+        }
+
+        /// The crates whose compatibility check failed when loading this module, if
+        /// the [`MismatchHandler`](crate::MismatchHandler) let it load anyway.
+        pub fn incompatible_crates(&self) -> &[&'static str] {
+            &self.incompatible_crates
+        }
+
+        /// Looks up a symbol exported by this module, typically an `extern "C"` function:
+        ///
+        /// ```rust,no_run
+        /// let module = rubicon::Module::load("libmod_a.so").unwrap();
+        /// let init: unsafe extern "C" fn() = unsafe { module.get("init").unwrap() };
+        /// unsafe { init() };
+        /// ```
+        ///
+        /// # Safety
+        ///
+        /// `T` must be the actual type of the symbol: a function pointer for a function,
+        /// or a reference/pointer for a static. Since the module is never unloaded, the
+        /// returned value stays valid for the lifetime of the process.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `T` isn't pointer-sized.
+        pub unsafe fn get<T: Copy>(&self, symbol: &str) -> Result<T, LoadError> {
+            assert_eq!(
+                std::mem::size_of::<T>(),
+                std::mem::size_of::<*mut c_void>(),
+                "rubicon: Module::get only returns pointer-sized values"
+            );
+
+            let address =
+                sys::symbol(self.handle, symbol).map_err(|message| LoadError::Symbol {
+                    path: self.path.clone(),
+                    symbol: symbol.to_string(),
+                    message,
+                })?;
+            Ok(std::mem::transmute_copy::<*mut c_void, T>(&address))
+        }
+    }
+
+    impl Module {
+        /// Looks up a function exported with [`entrypoint!`](crate::entrypoint), and
+        /// checks that it has the signature `F` (ABI included):
+        ///
+        /// ```rust,no_run
+        /// let module = rubicon::Module::load("libmod_a.so").unwrap();
+        /// let add = module.entrypoint::<fn(u32, u32) -> u32>("add").unwrap();
+        /// assert_eq!(add.call((1, 2)).unwrap(), 3);
+        /// ```
+        pub fn entrypoint<F: EntrypointSignature>(
+            &'static self,
+            name: &str,
+        ) -> Result<Entrypoint<F>, EntrypointError> {
+            let symbol = format!("{name}__RUBICON_ENTRYPOINT");
+            let descriptor: &'static EntrypointDescriptor =
+                unsafe { self.get(&symbol) }.map_err(EntrypointError::Load)?;
+
+            if descriptor.signature_hash() != crate::signature_hash::<F>() {
+                return Err(EntrypointError::SignatureMismatch {
+                    path: self.path.clone(),
+                    name: name.to_string(),
+                    expected: std::any::type_name::<F>().to_string(),
+                    found: descriptor.signature(),
+                });
+            }
+
+            Ok(Entrypoint {
+                module: self,
+                name: name.to_string(),
+                descriptor,
+                _signature: PhantomData,
+            })
+        }
+    }
+
+    /// A function exported by a module with [`entrypoint!`](crate::entrypoint), whose
+    /// signature was checked by [`Module::entrypoint`].
+    pub struct Entrypoint<F> {
+        module: &'static Module,
+        name: String,
+        descriptor: &'static EntrypointDescriptor,
+        _signature: PhantomData<F>,
+    }
+
+    impl<F: EntrypointSignature> Entrypoint<F> {
+        /// Calls the function. If it panics, the panic is caught on the module's side
+        /// and returned as [`EntrypointError::Panicked`].
+        pub fn call(&self, args: F::Args) -> Result<F::Output, EntrypointError> {
+            // SAFETY: the signature was checked in `Module::entrypoint`
+            unsafe { self.descriptor.call::<F>(args) }.map_err(|message| {
+                EntrypointError::Panicked {
+                    path: self.module.path.clone(),
+                    name: self.name.clone(),
+                    message,
+                }
+            })
+        }
+
+        /// The module this function was exported by
+        pub fn module(&self) -> &'static Module {
+            self.module
+        }
+    }
+
+    impl<F> Clone for Entrypoint<F> {
+        fn clone(&self) -> Self {
+            Self {
+                module: self.module,
+                name: self.name.clone(),
+                descriptor: self.descriptor,
+                _signature: PhantomData,
+            }
+        }
+    }
+
+    impl<F> fmt::Debug for Entrypoint<F> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Entrypoint")
+                .field("module", &self.module.path)
+                .field("name", &self.name)
+                .field("signature", &self.descriptor.signature())
+                .finish()
+        }
+    }
+
+    /// Why [`Module::entrypoint`] or [`Entrypoint::call`] failed.
+    #[derive(Debug)]
+    pub enum EntrypointError {
+        /// The module doesn't export this entrypoint
+        Load(LoadError),
+
+        /// The module exports this entrypoint with another signature
+        SignatureMismatch {
+            path: PathBuf,
+            name: String,
+            expected: String,
+            found: String,
+        },
+
+        /// The entrypoint panicked
+        Panicked {
+            path: PathBuf,
+            name: String,
+            message: String,
+        },
+    }
+
+    impl fmt::Display for EntrypointError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                EntrypointError::Load(e) => e.fmt(f),
+                EntrypointError::SignatureMismatch {
+                    path,
+                    name,
+                    expected,
+                    found,
+                } => write!(
+                    f,
+                    "rubicon: entrypoint {} of {} has signature `{}`, expected `{}`",
+                    name,
+                    path.display(),
+                    found,
+                    expected
+                ),
+                EntrypointError::Panicked {
+                    path,
+                    name,
+                    message,
+                } => write!(
+                    f,
+                    "rubicon: entrypoint {} of {} panicked: {}",
+                    name,
+                    path.display(),
+                    message
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for EntrypointError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                EntrypointError::Load(e) => Some(e),
+                _ => None,
+            }
+        }
+    }
+
+    impl fmt::Debug for Module {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Module")
+                .field("path", &self.path)
+                .field("crates", &self.crates)
+                .field("incompatible_crates", &self.incompatible_crates)
+                .finish()
+        }
+    }
+
+    /// Returns every module loaded with [`Module::load`] so far, in load order.
+    pub fn loaded_modules() -> Vec<&'static Module> {
+        MODULES.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Why [`Module::load`] or [`Module::get`] failed.
+    #[derive(Debug, Clone)]
+    pub enum LoadError {
+        /// The shared object couldn't be opened
+        Open { path: PathBuf, message: String },
+
+        /// The compatibility check of these crates failed, and the
+        /// [`MismatchHandler`](crate::MismatchHandler) didn't let the module load anyway
+        Incompatible { path: PathBuf, crates: Vec<String> },
+
+        /// The module imports globals from a crate whose compatibility info no loaded
+        /// shared object exports (with `import-globals-dlsym`)
+        MissingExport {
+            path: PathBuf,
+            crate_name: String,
+            symbol: String,
+        },
+
+        /// The module doesn't export the requested symbol
+        Symbol {
+            path: PathBuf,
+            symbol: String,
+            message: String,
+        },
+    }
+
+    impl fmt::Display for LoadError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                LoadError::Open { path, message } => {
+                    write!(f, "rubicon: could not load {}: {}", path.display(), message)
+                }
+                LoadError::Incompatible { path, crates } => write!(
+                    f,
+                    "rubicon: {} is incompatible with this process, for crate(s) {}",
+                    path.display(),
+                    crates.join(", ")
+                ),
+                LoadError::MissingExport {
+                    path,
+                    crate_name,
+                    symbol,
+                } => write!(
+                    f,
+                    "rubicon: {} imports globals from crate {}, but no loaded shared object exports {} (was the exporter, built with `export-globals`, loaded with global visibility?)",
+                    path.display(),
+                    crate_name,
+                    symbol
+                ),
+                LoadError::Symbol {
+                    path,
+                    symbol,
+                    message,
+                } => write!(
+                    f,
+                    "rubicon: could not find symbol {} in {}: {}",
+                    symbol,
+                    path.display(),
+                    message
+                ),
+            }
+        }
+    }
+
+    impl std::error::Error for LoadError {}
+
+    #[cfg(unix)]
+    mod sys {
+        use std::ffi::{c_void, CStr, CString};
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::Path;
+
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "freebsd",
+            target_os = "illumos",
+            target_os = "solaris",
+            target_vendor = "apple"
+        ))]
+        const FLAGS: libc::c_int = libc::RTLD_NOW | libc::RTLD_LOCAL | libc::RTLD_NODELETE;
+
+        #[cfg(not(any(
+            target_os = "linux",
+            target_os = "freebsd",
+            target_os = "illumos",
+            target_os = "solaris",
+            target_vendor = "apple"
+        )))]
+        const FLAGS: libc::c_int = libc::RTLD_NOW | libc::RTLD_LOCAL;
+
+        /// `error` is what `dlerror` returned: reading it clears it, so it can only be
+        /// read once.
+        fn error_message(error: *const libc::c_char) -> String {
+            if error.is_null() {
+                "unknown error".to_string()
+            } else {
+                unsafe { CStr::from_ptr(error) }
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        }
+
+        pub(super) fn open(path: &Path) -> Result<*mut c_void, String> {
+            let path = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+            let handle = unsafe { libc::dlopen(path.as_ptr(), FLAGS) };
+            if handle.is_null() {
+                Err(error_message(unsafe { libc::dlerror() }))
+            } else {
+                Ok(handle)
+            }
+        }
+
+        pub(super) fn symbol(handle: *mut c_void, name: &str) -> Result<*mut c_void, String> {
+            let name = CString::new(name).map_err(|e| e.to_string())?;
+            unsafe {
+                // clear any previous error, a null symbol isn't necessarily an error
+                libc::dlerror();
+                let address = libc::dlsym(handle, name.as_ptr());
+                if address.is_null() {
+                    let error = libc::dlerror();
+                    if !error.is_null() {
+                        return Err(error_message(error));
+                    }
+                }
+                Ok(address)
+            }
+        }
+
+        /// Returns true if `address` belongs to the shared object behind `handle`.
+        #[cfg(any(
+            target_os = "linux",
+            target_os = "freebsd",
+            target_os = "illumos",
+            target_os = "solaris",
+            target_vendor = "apple"
+        ))]
+        pub(super) fn contains(handle: *mut c_void, address: *const c_void) -> bool {
+            let Some(path) = crate::report::shared_object_path(address) else {
+                return false;
+            };
+            // only looks up an object that's already open, which is never closed anyway
+            let other = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_NOLOAD) };
+            other == handle
+        }
+
+        // no constructors there, so nothing gets checked outside of `Module::load`
+        #[cfg(not(any(
+            target_os = "linux",
+            target_os = "freebsd",
+            target_os = "illumos",
+            target_os = "solaris",
+            target_vendor = "apple"
+        )))]
+        pub(super) fn contains(_handle: *mut c_void, _address: *const c_void) -> bool {
+            false
+        }
+    }
+
+    #[cfg(windows)]
+    mod sys {
+        use std::ffi::{c_char, c_void, CString};
+        use std::os::windows::ffi::OsStrExt;
+        use std::path::Path;
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn LoadLibraryW(name: *const u16) -> *mut c_void;
+            fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+        }
+
+        pub(super) fn open(path: &Path) -> Result<*mut c_void, String> {
+            let path: Vec<u16> = path
+                .as_os_str()
+                .encode_wide()
+                .chain(std::iter::once(0))
+                .collect();
+            let handle = unsafe { LoadLibraryW(path.as_ptr()) };
+            if handle.is_null() {
+                Err(std::io::Error::last_os_error().to_string())
+            } else {
+                Ok(handle)
+            }
+        }
+
+        pub(super) fn symbol(handle: *mut c_void, name: &str) -> Result<*mut c_void, String> {
+            let name = CString::new(name).map_err(|e| e.to_string())?;
+            let address = unsafe { GetProcAddress(handle, name.as_ptr()) };
+            if address.is_null() {
+                Err(std::io::Error::last_os_error().to_string())
+            } else {
+                Ok(address)
+            }
+        }
+
+        // no constructors there, so nothing gets checked outside of `Module::load`
+        pub(super) fn contains(_handle: *mut c_void, _address: *const c_void) -> bool {
+            false
+        }
+    }
+}
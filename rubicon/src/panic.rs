@@ -0,0 +1,267 @@
+//! Forwarding panics raised in modules to the host's panic hook.
+//!
+//! Every cdylib links its own copy of the standard library, and with it, its own panic
+//! hook and panic count. A hook installed by the host with [`std::panic::set_hook`]
+//! never hears of panics raised in modules, and [`std::thread::panicking`] in the host
+//! doesn't know a module is unwinding.
+//!
+//! Modules built with `import-globals` replace their panic hook with one that hands
+//! the panic over to the exporter, through a table exported with the other globals.
+//! The exporter then raises it again on its side (and catches it right away), which
+//! runs whatever hook the host installed.
+
+use std::cell::RefCell;
+use std::fmt;
+
+/// A panic, as handed over from a module to the exporter.
+#[cfg_attr(not(feature = "export-globals"), allow(dead_code))]
+#[repr(C)]
+struct RawPanic {
+    module: *const u8,
+    module_len: usize,
+    message: *const u8,
+    message_len: usize,
+    file: *const u8,
+    file_len: usize,
+    line: u32,
+    column: u32,
+}
+
+/// What the exporter exports for modules to forward their panics. Only made of
+/// `extern "C"` functions, like [`AllocatorTable`](crate::AllocatorTable).
+#[cfg_attr(not(feature = "export-globals"), allow(dead_code))]
+#[repr(C)]
+struct PanicTable {
+    /// Runs the host's panic hook, returns false if it couldn't
+    forward: extern "C" fn(panic: &RawPanic) -> bool,
+    /// Registers a module's `std::thread::panicking`
+    register: extern "C" fn(panicking: extern "C" fn() -> bool),
+    /// Whether the current thread is panicking, in the exporter or any registered module
+    panicking: extern "C" fn() -> bool,
+}
+
+/// A panic raised in a module, as seen by the host's panic hook, see [`forwarded_panic`].
+#[derive(Clone, Debug)]
+pub struct ForwardedPanic {
+    module: String,
+    message: String,
+    file: String,
+    line: u32,
+    column: u32,
+}
+
+impl ForwardedPanic {
+    /// The path of the module that panicked
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    /// The panic message, or `Box<dyn Any>` if the payload wasn't a string
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The source file the module panicked in
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    /// The line the module panicked at
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// The column the module panicked at
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+}
+
+impl fmt::Display for ForwardedPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} panicked at {}:{}:{}:\n{}",
+            self.module, self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+std::thread_local! {
+    static FORWARDED: RefCell<Option<ForwardedPanic>> = const { RefCell::new(None) };
+}
+
+/// When called from the host's panic hook, returns the panic being forwarded from a
+/// module, if that's what the hook is running for.
+///
+/// Forwarded panics are raised again by rubicon, so [`std::panic::PanicHookInfo`]
+/// has the module's message as payload, but rubicon's own location. This has the
+/// module's:
+///
+/// ```rust
+/// std::panic::set_hook(Box::new(|info| match rubicon::forwarded_panic() {
+///     Some(panic) => eprintln!("{panic}"),
+///     None => eprintln!("{info}"),
+/// }));
+/// # let _ = std::panic::take_hook();
+/// ```
+pub fn forwarded_panic() -> Option<ForwardedPanic> {
+    FORWARDED.with(|forwarded| forwarded.borrow().clone())
+}
+
+//==============================================================================
+// Exporter side
+//==============================================================================
+
+#[cfg(feature = "export-globals")]
+mod exporter {
+    use super::{ForwardedPanic, PanicTable, RawPanic, FORWARDED};
+    use std::sync::Mutex;
+
+    #[export_name = "RUBICON_PANIC_HOOK__RUBICON_EXPORT"]
+    static RUBICON_PANIC_HOOK: PanicTable = PanicTable {
+        forward,
+        register,
+        panicking,
+    };
+
+    // The `std::thread::panicking` of every module that forwards its panics. Modules
+    // are never unloaded, so these stay valid.
+    static MODULES: Mutex<Vec<extern "C" fn() -> bool>> = Mutex::new(Vec::new());
+
+    extern "C" fn forward(panic: &RawPanic) -> bool {
+        // a panic while panicking aborts, and without unwinding, so does any panic
+        if !cfg!(panic = "unwind") || std::thread::panicking() {
+            return false;
+        }
+
+        let text = |ptr: *const u8, len: usize| {
+            String::from_utf8_lossy(unsafe { std::slice::from_raw_parts(ptr, len) }).into_owned()
+        };
+        let forwarded = ForwardedPanic {
+            module: text(panic.module, panic.module_len),
+            message: text(panic.message, panic.message_len),
+            file: text(panic.file, panic.file_len),
+            line: panic.line,
+            column: panic.column,
+        };
+        let message = forwarded.message.clone();
+
+        FORWARDED.with(|f| *f.borrow_mut() = Some(forwarded));
+        let _ = std::panic::catch_unwind(move || std::panic::panic_any(message));
+        FORWARDED.with(|f| f.borrow_mut().take());
+        true
+    }
+
+    extern "C" fn register(panicking: extern "C" fn() -> bool) {
+        MODULES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(panicking);
+    }
+
+    pub(super) extern "C" fn panicking() -> bool {
+        std::thread::panicking()
+            || MODULES
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .any(|panicking| panicking())
+    }
+}
+
+//==============================================================================
+// Module side
+//==============================================================================
+
+#[cfg(all(feature = "import-globals", not(feature = "import-globals-dlsym")))]
+fn panic_table() -> Option<&'static PanicTable> {
+    extern "Rust" {
+        #[link_name = "RUBICON_PANIC_HOOK__RUBICON_EXPORT"]
+        static RUBICON_PANIC_HOOK: PanicTable;
+    }
+    Some(unsafe { &RUBICON_PANIC_HOOK })
+}
+
+// Without an exporter, panics stay with the module's own hook.
+#[cfg(feature = "import-globals-dlsym")]
+fn panic_table() -> Option<&'static PanicTable> {
+    static TABLE: std::sync::OnceLock<Option<&'static PanicTable>> = std::sync::OnceLock::new();
+    *TABLE.get_or_init(|| {
+        crate::import::lookup("RUBICON_PANIC_HOOK__RUBICON_EXPORT\0")
+            .map(|address| unsafe { &*(address as *const PanicTable) })
+    })
+}
+
+#[cfg(feature = "import-globals")]
+fn forward(table: &PanicTable, info: &std::panic::PanicHookInfo<'_>) -> bool {
+    let module = crate::report::current_module_path().unwrap_or_else(|| "unknown_so".to_string());
+    let payload = info.payload();
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "Box<dyn Any>"
+    };
+    let (file, line, column) = info
+        .location()
+        .map_or(("<unknown>", 0, 0), |l| (l.file(), l.line(), l.column()));
+
+    (table.forward)(&RawPanic {
+        module: module.as_ptr(),
+        module_len: module.len(),
+        message: message.as_ptr(),
+        message_len: message.len(),
+        file: file.as_ptr(),
+        file_len: file.len(),
+        line,
+        column,
+    })
+}
+
+/// Makes this module forward its panics to the exporter, which runs the panic hook
+/// installed by the host. The module's previous hook only runs if the exporter can't
+/// take the panic (e.g. if the host is already panicking on this thread).
+///
+/// This is done by [`entrypoint!`](crate::entrypoint) before calling into the module,
+/// so it only needs to be called by modules that may panic elsewhere first (e.g. in
+/// threads of their own). It does nothing after the first call, and nothing at all
+/// outside of modules built with `import-globals`.
+pub fn forward_panics() {
+    #[cfg(feature = "import-globals")]
+    {
+        static FORWARDING: std::sync::Once = std::sync::Once::new();
+        FORWARDING.call_once(|| {
+            let Some(table) = panic_table() else {
+                return;
+            };
+
+            extern "C" fn module_panicking() -> bool {
+                std::thread::panicking()
+            }
+            (table.register)(module_panicking);
+
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                if !forward(table, info) {
+                    previous(info);
+                }
+            }));
+        });
+    }
+}
+
+/// Like [`std::thread::panicking`], but for the whole process: returns true if the
+/// current thread is unwinding, be it in the exporter or in any module that forwards
+/// its panics.
+pub fn panicking() -> bool {
+    #[cfg(feature = "export-globals")]
+    return exporter::panicking();
+
+    #[cfg(feature = "import-globals")]
+    return std::thread::panicking() || panic_table().is_some_and(|table| (table.panicking)());
+
+    #[cfg(not(any(feature = "export-globals", feature = "import-globals")))]
+    std::thread::panicking()
+}
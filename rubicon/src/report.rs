@@ -0,0 +1,693 @@
+//! Structured results of compatibility checks, and their human-readable rendering.
+
+use std::ffi::CStr;
+use std::fmt;
+
+/// Describes why a shared object is incompatible with the exporter of a crate's globals.
+///
+/// This is returned by [`check_compatibility`](crate::check_compatibility) (and the
+/// `check_compatibility` function generated by [`compatibility_check!`](crate::compatibility_check))
+/// when the exporter and the importer disagree on a crate's configuration, and by
+/// [`check_fingerprint`](crate::check_fingerprint) when they disagree on the type of a
+/// single global.
+///
+/// Its [`Display`](fmt::Display) implementation renders the same colorful table that
+/// rubicon panics with by default.
+#[derive(Debug, Clone)]
+pub struct CompatibilityReport {
+    /// The crate whose configuration (or global) doesn't match, e.g. `mokio`
+    pub crate_name: String,
+
+    /// For layout mismatches, the name of the global whose type doesn't match
+    pub global_name: Option<String>,
+
+    /// Path of the shared object that imports the crate's globals, e.g.
+    /// `/path/to/libmod_a.so`
+    pub module_path: String,
+
+    /// File name of the executable, e.g. `samplebin`
+    pub exe_name: String,
+
+    /// Every key known to either side, in order, whether it matches or not
+    pub entries: Vec<CompatibilityEntry>,
+}
+
+/// A single key of a [`CompatibilityReport`], with the value on each side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatibilityEntry {
+    /// e.g. `rustc-version`, `timer`
+    pub key: String,
+
+    /// The value exported alongside the globals, if any
+    pub exported: Option<String>,
+
+    /// The value expected by the importing shared object, if any
+    pub imported: Option<String>,
+}
+
+impl CompatibilityEntry {
+    /// Returns true if both sides have the same value for this key.
+    pub fn is_match(&self) -> bool {
+        self.exported == self.imported
+    }
+}
+
+impl CompatibilityReport {
+    /// Builds a report for the current shared object from pairs of exported and
+    /// imported values. Returns `None` if every key matches.
+    pub(crate) fn from_pairs(
+        crate_name: &str,
+        global_name: Option<&str>,
+        exported: &[(&str, &str)],
+        imported: &[(&str, &str)],
+    ) -> Option<Self> {
+        Self::compare(
+            crate_name,
+            global_name,
+            exported,
+            imported,
+            current_module_path().unwrap_or_else(|| "unknown_so".to_string()),
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.file_name().map(|s| s.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "unknown_exe".to_string()),
+        )
+    }
+
+    /// Like `from_pairs`, for shared objects other than the current one (used by
+    /// `rubicon-check`).
+    #[doc(hidden)]
+    pub fn compare(
+        crate_name: &str,
+        global_name: Option<&str>,
+        exported: &[(&str, &str)],
+        imported: &[(&str, &str)],
+        module_path: String,
+        exe_name: String,
+    ) -> Option<Self> {
+        // Gather all unique keys, exported first
+        let mut keys: Vec<&str> = Vec::new();
+        for (key, _) in exported.iter().chain(imported.iter()) {
+            if !keys.contains(key) {
+                keys.push(key);
+            }
+        }
+
+        let value_of = |pairs: &[(&str, &str)], key: &str| {
+            pairs
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        };
+        let entries: Vec<_> = keys
+            .into_iter()
+            .map(|key| CompatibilityEntry {
+                key: key.to_string(),
+                exported: value_of(exported, key),
+                imported: value_of(imported, key),
+            })
+            .collect();
+
+        if entries.iter().all(CompatibilityEntry::is_match) {
+            return None;
+        }
+
+        Some(Self {
+            crate_name: crate_name.to_string(),
+            global_name: global_name.map(|s| s.to_string()),
+            module_path,
+            exe_name,
+            entries,
+        })
+    }
+
+    /// The last component of [`Self::module_path`], e.g. `libmod_a.so`
+    pub fn module_name(&self) -> &str {
+        self.module_path
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or(&self.module_path)
+    }
+
+    /// The entries for which the exporter and the importer disagree.
+    pub fn mismatches(&self) -> impl Iterator<Item = &CompatibilityEntry> {
+        self.entries.iter().filter(|entry| !entry.is_match())
+    }
+}
+
+impl std::error::Error for CompatibilityReport {}
+
+//==============================================================================
+// C ABI
+//==============================================================================
+
+/// A [`CompatibilityReport`], as handed to a [`MismatchHandler`](crate::MismatchHandler).
+///
+/// The handler may live in a shared object built by another compiler, against
+/// another copy of the standard library, so the report crosses over with a C
+/// layout: borrowed strings, and a table of entries. It's only valid for the
+/// duration of the call: use [`RawReport::to_report`] to keep it around.
+#[repr(C)]
+pub struct RawReport {
+    crate_name: RawStr,
+    // null for configuration mismatches
+    global_name: RawStr,
+    module_path: RawStr,
+    exe_name: RawStr,
+    rendered: RawStr,
+    entries: *const RawEntry,
+    len: usize,
+}
+
+#[repr(C)]
+struct RawEntry {
+    key: RawStr,
+    // null when the key is missing from that side
+    exported: RawStr,
+    imported: RawStr,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawStr {
+    ptr: *const u8,
+    len: usize,
+}
+
+impl RawStr {
+    fn new(s: Option<&str>) -> Self {
+        match s {
+            Some(s) => Self {
+                ptr: s.as_ptr(),
+                len: s.len(),
+            },
+            None => Self {
+                ptr: std::ptr::null(),
+                len: 0,
+            },
+        }
+    }
+
+    // SAFETY: the caller ties the lifetime to the `RawReport` this was read from,
+    // which borrows from a live `CompatibilityReport`
+    unsafe fn get<'a>(self) -> Option<&'a str> {
+        if self.ptr.is_null() {
+            return None;
+        }
+        let bytes = std::slice::from_raw_parts(self.ptr, self.len);
+        Some(std::str::from_utf8(bytes).unwrap_or("<invalid UTF-8>"))
+    }
+}
+
+impl CompatibilityReport {
+    /// Calls `f` with a C view of this report, which borrows from it.
+    pub(crate) fn with_raw<R>(&self, f: impl FnOnce(&RawReport) -> R) -> R {
+        let rendered = self.to_string();
+        let entries: Vec<RawEntry> = self
+            .entries
+            .iter()
+            .map(|entry| RawEntry {
+                key: RawStr::new(Some(&entry.key)),
+                exported: RawStr::new(entry.exported.as_deref()),
+                imported: RawStr::new(entry.imported.as_deref()),
+            })
+            .collect();
+        f(&RawReport {
+            crate_name: RawStr::new(Some(&self.crate_name)),
+            global_name: RawStr::new(self.global_name.as_deref()),
+            module_path: RawStr::new(Some(&self.module_path)),
+            exe_name: RawStr::new(Some(&self.exe_name)),
+            rendered: RawStr::new(Some(&rendered)),
+            entries: entries.as_ptr(),
+            len: entries.len(),
+        })
+    }
+}
+
+impl RawReport {
+    /// The report, rendered the same way as [`CompatibilityReport`]'s `Display`
+    pub fn rendered(&self) -> &str {
+        unsafe { self.rendered.get() }.unwrap_or_default()
+    }
+
+    /// See [`CompatibilityReport::crate_name`]
+    pub fn crate_name(&self) -> &str {
+        unsafe { self.crate_name.get() }.unwrap_or_default()
+    }
+
+    /// See [`CompatibilityReport::global_name`]
+    pub fn global_name(&self) -> Option<&str> {
+        unsafe { self.global_name.get() }
+    }
+
+    /// See [`CompatibilityReport::module_path`]
+    pub fn module_path(&self) -> &str {
+        unsafe { self.module_path.get() }.unwrap_or_default()
+    }
+
+    /// Copies the report into this shared object's own [`CompatibilityReport`].
+    pub fn to_report(&self) -> CompatibilityReport {
+        let entries = if self.entries.is_null() {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.entries, self.len) }
+        };
+        CompatibilityReport {
+            crate_name: self.crate_name().to_string(),
+            global_name: self.global_name().map(|s| s.to_string()),
+            module_path: self.module_path().to_string(),
+            exe_name: unsafe { self.exe_name.get() }
+                .unwrap_or_default()
+                .to_string(),
+            entries: entries
+                .iter()
+                .map(|entry| unsafe {
+                    CompatibilityEntry {
+                        key: entry.key.get().unwrap_or_default().to_string(),
+                        exported: entry.exported.get().map(|s| s.to_string()),
+                        imported: entry.imported.get().map(|s| s.to_string()),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Returns the path of the shared object this copy of rubicon was linked into.
+#[cfg(all(unix, feature = "import-globals"))]
+pub(crate) fn current_module_path() -> Option<String> {
+    shared_object_path(current_module_path as *const std::ffi::c_void)
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Returns the path of the shared object `address` belongs to (code or data).
+/// Doesn't allocate.
+///
+/// Available whatever the features, since [`DebugAllocator`](crate::DebugAllocator)
+/// needs it too: hence `Dl_info` being declared here rather than taken from `libc`.
+#[cfg(unix)]
+pub(crate) fn shared_object_path(address: *const std::ffi::c_void) -> Option<&'static CStr> {
+    use std::ffi::{c_char, c_void};
+
+    // the same on every unix-like system that has `dladdr`
+    #[repr(C)]
+    struct DlInfo {
+        dli_fname: *const c_char,
+        dli_fbase: *mut c_void,
+        dli_sname: *const c_char,
+        dli_saddr: *mut c_void,
+    }
+
+    extern "C" {
+        fn dladdr(addr: *const c_void, info: *mut DlInfo) -> i32;
+    }
+
+    unsafe {
+        let mut info: DlInfo = std::mem::zeroed();
+        if dladdr(address, &mut info) != 0 && !info.dli_fname.is_null() {
+            // shared objects are never unloaded
+            return Some(CStr::from_ptr(info.dli_fname));
+        }
+    }
+    None
+}
+
+#[cfg(not(unix))]
+pub(crate) fn shared_object_path(_address: *const std::ffi::c_void) -> Option<&'static CStr> {
+    None
+}
+
+#[cfg(not(all(unix, feature = "import-globals")))]
+pub(crate) fn current_module_path() -> Option<String> {
+    None
+}
+
+//==============================================================================
+// Rendering
+//==============================================================================
+
+const RULE: &str = "\x1b[31m━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━\x1b[0m";
+
+struct AnsiEscape<D: fmt::Display>(u64, D);
+
+impl<D: fmt::Display> fmt::Display for AnsiEscape<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = format!("\x1b[{}m{}\x1b[0m", self.0, self.1);
+        f.pad(&inner)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct AnsiColor(u64);
+
+impl AnsiColor {
+    const BLUE: AnsiColor = AnsiColor(34);
+    const GREEN: AnsiColor = AnsiColor(32);
+    const RED: AnsiColor = AnsiColor(31);
+    const GREY: AnsiColor = AnsiColor(37);
+}
+
+fn colored<D: fmt::Display>(color: AnsiColor, d: D) -> AnsiEscape<D> {
+    AnsiEscape(color.0, d)
+}
+fn blue<D: fmt::Display>(d: D) -> AnsiEscape<D> {
+    colored(AnsiColor::BLUE, d)
+}
+fn red<D: fmt::Display>(d: D) -> AnsiEscape<D> {
+    colored(AnsiColor::RED, d)
+}
+
+// Helper function to count visible characters (ignoring ANSI escapes)
+fn visible_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut in_escape = false;
+    for c in s.chars() {
+        if c == '\x1b' {
+            in_escape = true;
+        } else if in_escape {
+            if c.is_alphabetic() {
+                in_escape = false;
+            }
+        } else {
+            len += 1;
+        }
+    }
+    len
+}
+
+struct Grid {
+    rows: Vec<Vec<String>>,
+    column_widths: Vec<usize>,
+}
+
+impl Grid {
+    fn new() -> Self {
+        Grid {
+            rows: Vec::new(),
+            column_widths: Vec::new(),
+        }
+    }
+
+    fn add_row(&mut self, row: Vec<String>) {
+        if self.column_widths.len() < row.len() {
+            self.column_widths.resize(row.len(), 0);
+        }
+        for (i, cell) in row.iter().enumerate() {
+            self.column_widths[i] = self.column_widths[i].max(visible_len(cell));
+        }
+        self.rows.push(row);
+    }
+
+    fn write_to(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total_width: usize =
+            self.column_widths.iter().sum::<usize>() + self.column_widths.len() * 3 - 1;
+
+        // Top border
+        writeln!(f, "┌{}┐", "─".repeat(total_width))?;
+
+        for (i, row) in self.rows.iter().enumerate() {
+            if i == 1 {
+                // Separator after header
+                writeln!(f, "╞{}╡", "═".repeat(total_width))?;
+            }
+
+            for (j, cell) in row.iter().enumerate() {
+                write!(
+                    f,
+                    "│ {}{} ",
+                    cell,
+                    " ".repeat(self.column_widths[j] - visible_len(cell))
+                )?;
+            }
+            writeln!(f, "│")?;
+        }
+
+        // Bottom border
+        writeln!(f, "└{}┘", "─".repeat(total_width))
+    }
+}
+
+struct MessageBox {
+    lines: Vec<String>,
+    max_width: usize,
+}
+
+impl MessageBox {
+    fn new() -> Self {
+        MessageBox {
+            lines: Vec::new(),
+            max_width: 0,
+        }
+    }
+
+    fn add_line(&mut self, line: String) {
+        self.max_width = self.max_width.max(visible_len(&line));
+        self.lines.push(line);
+    }
+
+    fn add_empty_line(&mut self) {
+        self.lines.push(String::new());
+    }
+
+    fn write_to(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let box_width = self.max_width + 4;
+
+        writeln!(f)?;
+        writeln!(f, "┌{}┐", "─".repeat(box_width - 2))?;
+
+        for line in &self.lines {
+            if line.is_empty() {
+                writeln!(f, "│{}│", " ".repeat(box_width - 2))?;
+            } else {
+                let padding = " ".repeat(box_width - 4 - visible_len(line));
+                writeln!(f, "│ {}{} │", line, padding)?;
+            }
+        }
+
+        write!(f, "└{}┘", "─".repeat(box_width - 2))
+    }
+}
+
+/// Something a module has to change about how it's built (or loaded) to get rid of
+/// a mismatch, depending on the keys that differ.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Fix {
+    Toolchain,
+    Target,
+    Profile,
+    Version,
+    Features,
+    Layout,
+    Exporter,
+}
+
+impl Fix {
+    fn of_key(key: &str) -> Self {
+        match key {
+            "llvm-version" => Fix::Toolchain,
+            key if key.starts_with("rustc-") => Fix::Toolchain,
+            "target-triple" | "target-features" => Fix::Target,
+            "opt-level" | "overflow-checks" | "debug-assertions" | "panic-strategy"
+            | "rustflags-hash" => Fix::Profile,
+            "pkg-version" => Fix::Version,
+            "missing-export" => Fix::Exporter,
+            // `feature:*`, and whatever keys were passed to `compatibility_check!`
+            _ => Fix::Features,
+        }
+    }
+
+    /// What the module needs to do, over two lines
+    fn lines(self, report: &CompatibilityReport) -> [String; 2] {
+        let exe_name = blue(&report.exe_name);
+        let crate_name = red(&report.crate_name);
+        match self {
+            Fix::Toolchain => [
+                "be built".to_string(),
+                format!("with the same toolchain as {exe_name}."),
+            ],
+            Fix::Target => [
+                "be built".to_string(),
+                format!("for the same target (and target features) as {exe_name}."),
+            ],
+            Fix::Profile => [
+                "be built".to_string(),
+                format!("with the same profile and RUSTFLAGS as {exe_name}."),
+            ],
+            Fix::Version => [
+                "depend on".to_string(),
+                format!("the same version of crate {crate_name} as {exe_name}."),
+            ],
+            Fix::Features => [
+                "enable".to_string(),
+                format!("the same cargo features as {exe_name} for crate {crate_name}."),
+            ],
+            Fix::Layout => [
+                format!(
+                    "agree with {exe_name} on the type of {}:",
+                    red(report.global_name.as_deref().unwrap_or_default())
+                ),
+                format!("use the same version and features of crate {crate_name}."),
+            ],
+            Fix::Exporter => [
+                "be loaded".to_string(),
+                format!("after the shared object that exports crate {crate_name}'s globals."),
+            ],
+        }
+    }
+
+    fn hint(self, report: &CompatibilityReport) -> Option<String> {
+        let crate_name = red(&report.crate_name);
+        match self {
+            Fix::Toolchain => {
+                Some("Pin it with a `rust-toolchain.toml` shared by both.".to_string())
+            }
+            Fix::Profile => Some("Compare their `[profile.*]` sections and RUSTFLAGS.".to_string()),
+            Fix::Version => Some(format!("Run `cargo tree -i {crate_name}` from both.")),
+            Fix::Features | Fix::Layout => Some(format!(
+                "Run `cargo tree -i {crate_name} -e features` from both."
+            )),
+            Fix::Exporter => Some(
+                "It must be built with `export-globals`, and loaded with RTLD_GLOBAL.".to_string(),
+            ),
+            Fix::Target => None,
+        }
+    }
+}
+
+fn format_column(primary: Option<&str>, secondary: Option<&str>, highlight: AnsiColor) -> String {
+    match primary {
+        Some(value) => {
+            if secondary == Some(value) {
+                colored(AnsiColor::GREY, value).to_string()
+            } else {
+                colored(highlight, value).to_string()
+            }
+        }
+        None => colored(AnsiColor::RED, "∅").to_string(),
+    }
+}
+
+impl fmt::Display for CompatibilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let so_name = self.module_name();
+        let exe_name = &self.exe_name;
+        let crate_name = &self.crate_name;
+
+        writeln!(f)?;
+        writeln!(f, "{RULE}")?;
+        match &self.global_name {
+            Some(global_name) => {
+                writeln!(
+                    f,
+                    " 💀 Layout mismatch for global {} of crate {}\n",
+                    red(global_name),
+                    red(crate_name)
+                )?;
+                writeln!(
+                    f,
+                    "{} disagrees with {} on the type of {}.\n",
+                    blue(so_name),
+                    blue(exe_name),
+                    red(global_name)
+                )?;
+            }
+            None => {
+                writeln!(f, " 💀 Feature mismatch for crate {}\n", red(crate_name))?;
+                writeln!(
+                    f,
+                    "{} has an incompatible configuration for {}.\n",
+                    blue(so_name),
+                    red(crate_name)
+                )?;
+            }
+        }
+
+        let mut grid = Grid::new();
+
+        // Add header
+        grid.add_row(vec![
+            "Key".to_string(),
+            format!("Binary {}", blue(exe_name)),
+            format!("Module {}", blue(so_name)),
+        ]);
+
+        for entry in &self.entries {
+            let exported = entry.exported.as_deref();
+            let imported = entry.imported.as_deref();
+            grid.add_row(vec![
+                colored(AnsiColor::GREY, &entry.key).to_string(),
+                format_column(exported, imported, AnsiColor::GREEN),
+                format_column(imported, exported, AnsiColor::RED),
+            ]);
+        }
+
+        grid.write_to(f)?;
+
+        writeln!(f)?;
+        match &self.global_name {
+            Some(_) => {
+                writeln!(
+                    f,
+                    "Different type layouts would lead to memory corruption.\n"
+                )?;
+            }
+            None => {
+                writeln!(
+                    f,
+                    "Different feature sets may result in different struct layouts, which"
+                )?;
+                writeln!(f, "would lead to memory corruption.\n")?;
+            }
+        }
+
+        writeln!(
+            f,
+            "More info: \x1b[4m\x1b[34mhttps://crates.io/crates/rubicon\x1b[0m"
+        )?;
+
+        let mut fixes: Vec<Fix> = Vec::new();
+        if self.global_name.is_some() {
+            fixes.push(Fix::Layout);
+        } else {
+            for entry in self.mismatches() {
+                let fix = Fix::of_key(&entry.key);
+                if !fixes.contains(&fix) {
+                    fixes.push(fix);
+                }
+            }
+        }
+
+        let mut message_box = MessageBox::new();
+        match fixes.as_slice() {
+            [fix] => {
+                let [first, second] = fix.lines(self);
+                message_box.add_line(format!(
+                    "To fix this issue, {} needs to {}",
+                    blue(so_name),
+                    first
+                ));
+                message_box.add_line(second);
+            }
+            fixes => {
+                message_box.add_line(format!("To fix this issue, {} needs to:", blue(so_name)));
+                for fix in fixes {
+                    let [first, second] = fix.lines(self);
+                    message_box.add_line(format!("  - {first} {second}"));
+                }
+            }
+        }
+
+        let hints: Vec<_> = fixes.iter().filter_map(|fix| fix.hint(self)).collect();
+        if !hints.is_empty() {
+            message_box.add_empty_line();
+            message_box.add_line("\x1b[34mHINT:\x1b[0m".to_string());
+            for hint in hints {
+                message_box.add_line(hint);
+            }
+        }
+
+        message_box.write_to(f)?;
+        writeln!(f)?;
+        writeln!(f, "{RULE}")
+    }
+}
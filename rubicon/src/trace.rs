@@ -0,0 +1,65 @@
+//! `RUBICON_TRACE`: where imported globals were found.
+//!
+//! With `RUBICON_TRACE=1`, the first access to every imported global prints which
+//! copy of it the module ended up with, and whether the compatibility check passed:
+//!
+//! ```text
+//! rubicon: trace: ../mod_a/target/debug/libmod_a.so accesses mokio::MOKIO_PL1 at 0x7f466057f710 in /path/to/libexports.so (compatibility check passed)
+//! ```
+
+use std::ffi::c_void;
+use std::sync::{Mutex, OnceLock};
+
+use crate::CompatibilityReport;
+
+/// The environment variable that enables tracing, when set to anything but `0`
+pub(crate) const ENV_VAR: &str = "RUBICON_TRACE";
+
+pub(crate) fn enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| std::env::var_os(ENV_VAR).is_some_and(|v| !v.is_empty() && v != "0"))
+}
+
+// The crates (and, for layout mismatches, the globals) whose check failed in this
+// shared object, whatever the mismatch handler did about it.
+static MISMATCHES: Mutex<Vec<(String, Option<String>)>> = Mutex::new(Vec::new());
+
+/// Called by `report_mismatch`, so that accesses traced afterwards can say the check failed.
+pub(crate) fn record_mismatch(report: &CompatibilityReport) {
+    if !enabled() {
+        return;
+    }
+    MISMATCHES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((report.crate_name.clone(), report.global_name.clone()));
+}
+
+/// Prints where `global` was found, if tracing is enabled. `address` is that of the
+/// exported symbol, and must be called after the global's compatibility checks ran.
+pub(crate) fn first_access(crate_name: &str, global_name: &str, address: *const c_void) {
+    if !enabled() {
+        return;
+    }
+
+    let module = crate::report::current_module_path().unwrap_or_else(|| "unknown_so".to_string());
+    let object = crate::report::shared_object_path(address).map_or_else(
+        || "an unknown object".to_string(),
+        |path| path.to_string_lossy().into_owned(),
+    );
+    let check = if cfg!(feature = "no-compatibility-checks-yolo") {
+        "skipped"
+    } else if MISMATCHES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .any(|(c, g)| c == crate_name && g.as_deref().is_none_or(|g| g == global_name))
+    {
+        "failed"
+    } else {
+        "passed"
+    };
+    eprintln!(
+        "rubicon: trace: {module} accesses {crate_name}::{global_name} at {address:?} in {object} (compatibility check {check})"
+    );
+}
@@ -1,12 +1,103 @@
 use soprintln::soprintln;
-use std::sync::atomic::Ordering;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
 
-#[no_mangle]
-pub extern "Rust" fn init() {
-    soprintln::init!();
-    mokio::MOKIO_TL1.with(|s| s.fetch_add(1, Ordering::Relaxed));
-    mokio::MOKIO_PL1.fetch_add(1, Ordering::Relaxed);
+rubicon::entrypoint! {
+    pub fn init() {
+        soprintln::init!();
+        mokio::MOKIO_TL1.with(|s| s.fetch_add(1, Ordering::Relaxed));
+        mokio::MOKIO_PL1.fetch_add(1, Ordering::Relaxed);
+        mokio::MOKIO_TL3.with(|rt| rt.borrow_mut().counter += 1);
+        mokio::MOKIO_TL4.set(mokio::MOKIO_TL4.get() + 1);
 
-    let dangerous = mokio::inc_dangerous();
-    soprintln!("DANGEROUS is now {}", dangerous);
+        let dangerous = mokio::inc_dangerous();
+        soprintln!("DANGEROUS is now {}", dangerous);
+        mokio::LESS_DANGEROUS.with(|v| *v += 1);
+        let lazy: &u64 = &mokio::MOKIO_LAZY;
+        assert_eq!(*lazy, 42);
+        mokio::next_id();
+
+        #[cfg(feature = "missing-global")]
+        missing::MOD_A_NOT_EXPORTED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// memory allocated here may be freed by the host, and vice versa
+#[cfg(not(feature = "debug-allocator"))]
+#[global_allocator]
+static ALLOCATOR: rubicon::SharedAllocator = rubicon::SharedAllocator;
+
+#[cfg(feature = "debug-allocator")]
+#[global_allocator]
+static ALLOCATOR: rubicon::DebugAllocator<std::alloc::System> =
+    rubicon::DebugAllocator::new(std::alloc::System);
+
+rubicon::entrypoint! {
+    /// Returns a buffer for the host to free.
+    pub fn allocate(len: usize) -> Vec<u8> {
+        vec![0xAB; len]
+    }
+}
+
+rubicon::entrypoint! {
+    /// Panics, for the host to check that its panic hook hears of it.
+    pub fn panic_now() {
+        panic!("mod_a panics on purpose");
+    }
+}
+
+std::thread_local! {
+    static LOCAL_TL1: AtomicU64 = const { AtomicU64::new(0) };
+}
+
+rubicon::entrypoint! {
+    /// Times `iterations` accesses to an imported thread-local, then to a plain
+    /// `std::thread_local!`, returning both durations in nanoseconds.
+    pub fn bench(iterations: u64) -> (u64, u64) {
+        // the first access runs the compatibility checks: keep it out of the loop
+        mokio::MOKIO_TL1.with(|s| s.load(Ordering::Relaxed));
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            black_box(&mokio::MOKIO_TL1).with(|s| s.fetch_add(1, Ordering::Relaxed));
+        }
+        let imported = start.elapsed().as_nanos() as u64;
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            black_box(&LOCAL_TL1).with(|s| s.fetch_add(1, Ordering::Relaxed));
+        }
+        let local = start.elapsed().as_nanos() as u64;
+
+        (imported, local)
+    }
+}
+
+#[cfg(feature = "missing-global")]
+mod missing {
+    rubicon::process_local! {
+        pub static MOD_A_NOT_EXPORTED: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    }
+}
+
+// nobody exports mod_a's globals, so nobody exports its compatibility info either
+#[cfg(feature = "missing-global")]
+rubicon::compatibility_check! {}
+
+// Run by the test harness with `rubicon-fallback`: there's no exporter in a test
+// binary, so every global mod_a uses (`static mut` ones included) is a local instance.
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn globals_fall_back_to_local_instances() {
+        mokio::MOKIO_PL1.fetch_add(1, Ordering::Relaxed);
+        mokio::MOKIO_TL1.with(|s| s.fetch_add(1, Ordering::Relaxed));
+        assert_eq!(mokio::inc_dangerous(), 1);
+        assert_eq!(mokio::get_dangerous(), 1);
+        let lazy: &u64 = &mokio::MOKIO_LAZY;
+        assert_eq!(*lazy, 42);
+    }
 }
@@ -1,12 +1,30 @@
 use soprintln::soprintln;
 use std::sync::atomic::Ordering;
 
-#[no_mangle]
-pub extern "Rust" fn init() {
-    soprintln::init!();
-    mokio::MOKIO_TL1.with(|s| s.fetch_add(1, Ordering::Relaxed));
-    mokio::MOKIO_PL1.fetch_add(1, Ordering::Relaxed);
-
-    let dangerous = mokio::inc_dangerous();
-    soprintln!("DANGEROUS is now {}", dangerous);
+rubicon::entrypoint! {
+    pub fn init() {
+        soprintln::init!();
+        mokio::MOKIO_TL1.with(|s| s.fetch_add(1, Ordering::Relaxed));
+        mokio::MOKIO_PL1.fetch_add(1, Ordering::Relaxed);
+        mokio::MOKIO_TL3.with(|rt| rt.borrow_mut().counter += 1);
+
+        let dangerous = mokio::inc_dangerous();
+        soprintln!("DANGEROUS is now {}", dangerous);
+        mokio::LESS_DANGEROUS.with(|v| *v += 1);
+        let lazy: &u64 = &mokio::MOKIO_LAZY;
+        assert_eq!(*lazy, 42);
+        mokio::next_id();
+    }
+}
+
+rubicon::entrypoint! {
+    /// Frees a buffer allocated elsewhere.
+    pub fn free(buffer: Vec<u8>) {
+        drop(buffer);
+    }
 }
+
+#[cfg(feature = "debug-allocator")]
+#[global_allocator]
+static ALLOCATOR: rubicon::DebugAllocator<std::alloc::System> =
+    rubicon::DebugAllocator::new(std::alloc::System);
@@ -0,0 +1,7 @@
+fn main() {
+    rubicon::build::CompatibilityInfo::from_env()
+        // deliberately left out so that the type fingerprint of `MOKIO_TL3`
+        // is what catches it
+        .ignore_feature("instrumented")
+        .emit();
+}
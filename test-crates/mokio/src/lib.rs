@@ -1,13 +1,14 @@
-use std::sync::{atomic::AtomicU64, Arc, Mutex};
+use std::{
+    cell::{Cell, RefCell},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
+// the crate version and enabled features are added by `build.rs`,
+// these are on top of that.
 rubicon::compatibility_check! {
-    ("mokio_pkg_version", env!("CARGO_PKG_VERSION")),
-
-    #[cfg(not(feature = "timer"))]
-    ("timer", "disabled"),
-    #[cfg(feature = "timer")]
-    ("timer", "enabled"),
-
     #[cfg(not(feature = "timer"))]
     ("timer_is_disabled", "1"),
 }
@@ -25,8 +26,14 @@ pub struct Runtime {
     #[allow(dead_code)]
     timer: TimerInternals,
 
-    // this field is second on purpose so that it'll be offset
-    // if the feature is enabled/disabled
+    // `instrumented` is deliberately left out of the compatibility info (see
+    // `build.rs`): the type fingerprint of `MOKIO_TL3` should catch it anyway.
+    #[cfg(feature = "instrumented")]
+    #[allow(dead_code)]
+    instrumentation: [u64; 2],
+
+    // this field is after the others on purpose so that it'll be offset
+    // if the features are enabled/disabled
     pub counter: u64,
 }
 
@@ -36,11 +43,34 @@ rubicon::process_local! {
 
     pub static mut DANGEROUS: u64 = 0;
     static DANGEROUS_MUTEX: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    // like `DANGEROUS`, without the `unsafe`
+    pub static LESS_DANGEROUS: rubicon::StaticMut<u64> = rubicon::StaticMut::new(0);
+
+    pub static MOKIO_LAZY_INITS: AtomicU64 = AtomicU64::new(0);
+}
+
+rubicon::lazy_process_local! {
+    // initialized by whichever shared object gets to it first, and only once
+    pub static MOKIO_LAZY: u64 = {
+        MOKIO_LAZY_INITS.fetch_add(1, Ordering::Relaxed);
+        42
+    };
 }
 
 rubicon::thread_local! {
     pub static MOKIO_TL1: AtomicU64 = AtomicU64::new(0);
     pub static MOKIO_TL2: Arc<Mutex<Runtime>> = Arc::new(Mutex::new(Runtime::default()));
+    pub static MOKIO_TL3: RefCell<Runtime> = RefCell::new(Runtime::default());
+    pub static MOKIO_TL4: Cell<u64> = const { Cell::new(0) };
+}
+
+rubicon::process_fn! {
+    /// Every call goes to the exporter's copy, along with its `NEXT_ID`.
+    pub fn next_id() -> u64 {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        NEXT_ID.fetch_add(1, Ordering::Relaxed) + 1
+    }
 }
 
 pub fn inc_dangerous() -> u64 {
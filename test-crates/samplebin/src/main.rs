@@ -1,28 +1,83 @@
-use std::sync::atomic::Ordering;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 use exports::{self as _, mokio};
 use soprintln::soprintln;
 
+// A custom global allocator: with `rubicon::SharedAllocator`, modules allocate
+// through it too, so the host can free what they allocate.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+rubicon::global_allocator! {
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+}
+
 fn main() {
     struct ModuleSpec {
         name: &'static str,
         channel: String,
+        profile: String,
         features: Vec<String>,
+        rustflags: Vec<String>,
+        opt_level: Option<String>,
     }
 
+    // modules are built with the same profile as the binary, unless overridden
+    let default_profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+
     let mut modules = [
         ModuleSpec {
             name: "mod_a",
             channel: "stable".to_string(),
+            profile: default_profile.to_string(),
             features: Default::default(),
+            rustflags: Default::default(),
+            opt_level: None,
         },
         ModuleSpec {
             name: "mod_b",
             channel: "stable".to_string(),
+            profile: default_profile.to_string(),
             features: Default::default(),
+            rustflags: Default::default(),
+            opt_level: None,
         },
     ];
 
+    // looks up `init` with the wrong signature, to check that it's refused
+    let mut extern_c_init = false;
+
+    // opens mod_a with a plain `dlopen` before `Module::load`, which then has nothing
+    // left to check: the module's constructors do it
+    let mut dlopen_a = false;
+
+    // loads mod_a a second time, which must give the same result as the first
+    let mut load_twice = false;
+
+    // hands a buffer allocated by mod_a to mod_b, for it to free
+    let mut cross_free = false;
+
+    // compares imported globals with plain `std::thread_local!`, instead of the usual checks
+    let mut bench = false;
+
     for arg in std::env::args().skip(1) {
         if let Some(rest) = arg.strip_prefix("--features:") {
             let parts: Vec<&str> = rest.splitn(2, '=').collect();
@@ -57,6 +112,68 @@ fn main() {
                 .find(|m| m.name == mod_name)
                 .unwrap_or_else(|| panic!("Unknown module: {}", mod_name));
             module.channel = channel.to_string();
+        } else if let Some(rest) = arg.strip_prefix("--profile:") {
+            let parts: Vec<&str> = rest.splitn(2, '=').collect();
+            if parts.len() != 2 {
+                panic!("Invalid argument format: expected --profile:module=(debug|release)");
+            }
+            let mod_name = parts[0];
+            let profile = parts[1];
+            if profile != "debug" && profile != "release" {
+                panic!(
+                    "Invalid profile: {}. Expected 'debug' or 'release'",
+                    profile
+                );
+            }
+            let module = modules
+                .iter_mut()
+                .find(|m| m.name == mod_name)
+                .unwrap_or_else(|| panic!("Unknown module: {}", mod_name));
+            module.profile = profile.to_string();
+        } else if let Some(rest) = arg.strip_prefix("--rustflags:") {
+            let parts: Vec<&str> = rest.splitn(2, '=').collect();
+            if parts.len() != 2 {
+                panic!("Invalid argument format: expected --rustflags:module=flags");
+            }
+            let mod_name = parts[0];
+            let module = modules
+                .iter_mut()
+                .find(|m| m.name == mod_name)
+                .unwrap_or_else(|| panic!("Unknown module: {}", mod_name));
+            module.rustflags.push(parts[1].to_string());
+        } else if let Some(rest) = arg.strip_prefix("--opt-level:") {
+            let parts: Vec<&str> = rest.splitn(2, '=').collect();
+            if parts.len() != 2 {
+                panic!("Invalid argument format: expected --opt-level:module=level");
+            }
+            let mod_name = parts[0];
+            let module = modules
+                .iter_mut()
+                .find(|m| m.name == mod_name)
+                .unwrap_or_else(|| panic!("Unknown module: {}", mod_name));
+            module.opt_level = Some(parts[1].to_string());
+        } else if arg == "--extern-c-init" {
+            extern_c_init = true;
+        } else if arg == "--dlopen" {
+            dlopen_a = true;
+        } else if arg == "--load-twice" {
+            load_twice = true;
+        } else if arg == "--cross-free" {
+            cross_free = true;
+        } else if arg == "--bench" {
+            bench = true;
+        } else if let Some(handler) = arg.strip_prefix("--on-mismatch=") {
+            let handler: rubicon::MismatchHandler = match handler {
+                "panic" => rubicon::panic_on_mismatch,
+                "abort" => rubicon::abort_on_mismatch,
+                "warn" => rubicon::warn_on_mismatch,
+                "list-modules" => list_modules_on_mismatch,
+                _ => panic!(
+                    "Invalid mismatch handler: {}. Expected 'panic', 'abort', 'warn' or 'list-modules'",
+                    handler
+                ),
+            };
+            rubicon::set_mismatch_handler(handler);
         } else {
             panic!("Unknown argument: {}", arg);
         }
@@ -75,7 +192,7 @@ fn main() {
 
     soprintln!("app starting up...");
 
-    for module in modules {
+    for module in &modules {
         cfg_if::cfg_if! {
             if #[cfg(target_os = "macos")] {
                 let rustflags = "-Clink-arg=-undefined -Clink-arg=dynamic_lookup";
@@ -85,12 +202,28 @@ fn main() {
                 let rustflags = "";
             }
         }
+        let rustflags = std::iter::once(rustflags)
+            .chain(module.rustflags.iter().map(|s| s.as_str()))
+            .collect::<Vec<_>>()
+            .join(" ");
 
         let mut cmd = std::process::Command::new("cargo");
         cmd.arg(format!("+{}", module.channel))
             .arg("build")
-            .env("RUSTFLAGS", rustflags)
+            .env("RUSTFLAGS", rustflags.trim())
             .current_dir(format!("../{}", module.name));
+        if module.profile == "release" {
+            cmd.arg("--release");
+        }
+        if let Some(opt_level) = &module.opt_level {
+            // only the opt-level changes, unlike with `--profile`
+            let profile = if module.profile == "release" {
+                "RELEASE"
+            } else {
+                "DEV"
+            };
+            cmd.env(format!("CARGO_PROFILE_{profile}_OPT_LEVEL"), opt_level);
+        }
         if !module.features.is_empty() {
             cmd.arg("--features").arg(module.features.join(","));
         }
@@ -107,7 +240,7 @@ fn main() {
         }
     }
 
-    fn module_path(name: &str) -> String {
+    fn module_path(module: &ModuleSpec) -> String {
         #[cfg(target_os = "windows")]
         let prefix = "";
         #[cfg(not(target_os = "windows"))]
@@ -121,21 +254,120 @@ fn main() {
         let extension = "so";
 
         format!(
-            "../mod_{}/target/debug/{}mod_{}.{}",
-            name, prefix, name, extension
+            "../{}/target/{}/{}{}.{}",
+            module.name, module.profile, prefix, module.name, extension
         )
     }
 
     soprintln!("loading modules...");
-    let lib_a = unsafe { libloading::Library::new(module_path("a")).unwrap() };
-    let lib_a = Box::leak(Box::new(lib_a));
-    let init_a: libloading::Symbol<unsafe extern "C" fn()> = unsafe { lib_a.get(b"init").unwrap() };
-    let init_a = Box::leak(Box::new(init_a));
+    if dlopen_a {
+        #[cfg(unix)]
+        {
+            let path = std::ffi::CString::new(module_path(&modules[0])).unwrap();
+            let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW) };
+            assert!(!handle.is_null(), "Failed to dlopen mod_a");
+        }
+        #[cfg(not(unix))]
+        panic!("--dlopen is only supported on unix-like systems");
+    }
+    let mut lib_a = rubicon::Module::load(module_path(&modules[0]));
+    if load_twice {
+        let again = rubicon::Module::load(module_path(&modules[0]));
+        assert_eq!(format!("{:?}", lib_a), format!("{:?}", again));
+        lib_a = again;
+    }
+    let lib_a = lib_a.unwrap();
+    if dlopen_a {
+        // the checks ran in the constructors, but they still count
+        assert_eq!(lib_a.crates(), ["mokio"]);
+    }
+    match unsafe { lib_a.get::<*const u8>("no_such_symbol") } {
+        Err(rubicon::LoadError::Symbol { message, .. }) => {
+            assert!(
+                message.contains("no_such_symbol"),
+                "unhelpful error: {}",
+                message
+            )
+        }
+        other => panic!("expected a symbol error, got {:?}", other.map(|_| ())),
+    }
+    if extern_c_init {
+        lib_a.entrypoint::<extern "C" fn()>("init").unwrap();
+    }
+    let init_a = lib_a.entrypoint::<fn()>("init").unwrap();
 
-    let lib_b = unsafe { libloading::Library::new(module_path("b")).unwrap() };
-    let lib_b = Box::leak(Box::new(lib_b));
-    let init_b: libloading::Symbol<unsafe extern "C" fn()> = unsafe { lib_b.get(b"init").unwrap() };
-    let init_b = Box::leak(Box::new(init_b));
+    let lib_b = rubicon::Module::load(module_path(&modules[1])).unwrap();
+    let init_b = lib_b.entrypoint::<fn()>("init").unwrap();
+
+    let allocate_a = lib_a
+        .entrypoint::<fn(usize) -> Vec<u8>>("allocate")
+        .unwrap();
+    if cross_free {
+        let free_b = lib_b.entrypoint::<fn(Vec<u8>)>("free").unwrap();
+        free_b.call((allocate_a.call((16,)).unwrap(),)).unwrap();
+        soprintln!("mod_b freed a buffer allocated by mod_a");
+    }
+
+    // mod_a allocates with `rubicon::SharedAllocator`, ie. with our allocator
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let buffer = allocate_a.call((1 << 20,)).unwrap();
+    assert!(ALLOCATED.load(Ordering::Relaxed) - before >= 1 << 20);
+    assert!(buffer.iter().all(|&b| b == 0xAB));
+    drop(buffer);
+
+    // mod_a panics with its own copy of the standard library, but our panic hook
+    // hears of it all the same
+    static PANICS: Mutex<Vec<rubicon::ForwardedPanic>> = Mutex::new(Vec::new());
+    std::panic::set_hook(Box::new(|_| {
+        if let Some(panic) = rubicon::forwarded_panic() {
+            assert!(rubicon::panicking());
+            PANICS.lock().unwrap().push(panic);
+        }
+    }));
+    let panic_a = lib_a.entrypoint::<fn()>("panic_now").unwrap();
+    match panic_a.call(()) {
+        Err(rubicon::EntrypointError::Panicked { message, .. }) => {
+            assert_eq!(message, "mod_a panics on purpose")
+        }
+        _ => panic!("mod_a was expected to panic"),
+    }
+    drop(std::panic::take_hook());
+    {
+        let panics = PANICS.lock().unwrap();
+        assert_eq!(panics.len(), 1);
+        assert_eq!(panics[0].message(), "mod_a panics on purpose");
+        assert!(panics[0].module().contains("mod_a"));
+        assert!(panics[0].file().ends_with("lib.rs"));
+        soprintln!("our panic hook saw: {}", panics[0]);
+    }
+
+    for module in rubicon::loaded_modules() {
+        soprintln!(
+            "loaded {} (checked: {:?})",
+            module.path().display(),
+            module.crates()
+        );
+    }
+
+    if bench {
+        const ITERATIONS: u64 = 10_000_000;
+        let bench_a = lib_a.entrypoint::<fn(u64) -> (u64, u64)>("bench").unwrap();
+        let (imported, local) = bench_a.call((ITERATIONS,)).unwrap();
+        let per_access = |nanos: u64| nanos as f64 / ITERATIONS as f64;
+        println!(
+            "imported thread-local: {:.3} ns/access, std::thread_local!: {:.3} ns/access",
+            per_access(imported),
+            per_access(local),
+        );
+        // debug builds don't inline anything, so only hold release builds to it
+        if !cfg!(debug_assertions) {
+            assert!(
+                imported <= 2 * local,
+                "imported thread-locals should be within 2x of std::thread_local!"
+            );
+        }
+        return;
+    }
 
     soprintln!(
         "PL1 = {}, TL1 = {} (initial)",
@@ -144,14 +376,14 @@ fn main() {
     );
 
     for _ in 0..2 {
-        unsafe { init_a() };
+        init_a.call(()).unwrap();
         soprintln!(
             "PL1 = {}, TL1 = {} (after init_a)",
             mokio::MOKIO_PL1.load(Ordering::Relaxed),
             mokio::MOKIO_TL1.with(|s| s.load(Ordering::Relaxed)),
         );
 
-        unsafe { init_b() };
+        init_b.call(()).unwrap();
         soprintln!(
             "PL1 = {}, TL1 = {} (after init_b)",
             mokio::MOKIO_PL1.load(Ordering::Relaxed),
@@ -159,12 +391,15 @@ fn main() {
         );
     }
 
+    // MOKIO_TL4 is const-initialized, and only incremented by `init_a()`
+    assert_eq!(mokio::MOKIO_TL4.get(), 2);
+
     soprintln!("now starting a couple threads");
 
     let mut join_handles = vec![];
     for id in 1..=3 {
-        let init_a = &*init_a;
-        let init_b = &*init_b;
+        let init_a = init_a.clone();
+        let init_b = init_b.clone();
 
         let thread_name = format!("worker-{}", id);
         let jh = std::thread::Builder::new()
@@ -179,14 +414,14 @@ fn main() {
                 );
 
                 for _ in 0..2 {
-                    unsafe { init_a() };
+                    init_a.call(()).unwrap();
                     soprintln!(
                         "PL1 = {}, TL1 = {} (after init_a)",
                         mokio::MOKIO_PL1.load(Ordering::Relaxed),
                         mokio::MOKIO_TL1.with(|s| s.load(Ordering::Relaxed)),
                     );
 
-                    unsafe { init_b() };
+                    init_b.call(()).unwrap();
                     soprintln!(
                         "PL1 = {}, TL1 = {} (after init_b)",
                         mokio::MOKIO_PL1.load(Ordering::Relaxed),
@@ -215,4 +450,25 @@ fn main() {
 
     // same for DANGEROUS, it's just guarded by a mutex internally
     assert_eq!(mokio::get_dangerous(), 16);
+    assert_eq!(mokio::LESS_DANGEROUS.get(), 16);
+
+    // MOKIO_LAZY was accessed from both modules, on several threads, but the
+    // exporter's initializer only ran once
+    assert_eq!(mokio::MOKIO_LAZY_INITS.load(Ordering::Relaxed), 1);
+
+    // mod_a and mod_b called the exporter's `next_id` 16 times in total
+    assert_eq!(mokio::next_id(), 17);
+}
+
+// Lists the modules loaded so far, which must not deadlock while a module is being
+// loaded, then refuses the module.
+extern "C" fn list_modules_on_mismatch(report: *const rubicon::RawReport) -> rubicon::MismatchAction {
+    let report = unsafe { &*report };
+    let loaded: Vec<_> = rubicon::loaded_modules()
+        .iter()
+        .map(|module| module.path().display().to_string())
+        .collect();
+    eprintln!("{}", report.rendered());
+    eprintln!("mismatch while these modules were loaded: {:?}", loaded);
+    rubicon::MismatchAction::Panic
 }
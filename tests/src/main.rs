@@ -6,12 +6,14 @@ use std::process::{Command, Stdio};
 #[derive(Clone, Default)]
 struct EnvVars {
     library_search_paths: Vec<String>,
+    extra_vars: Vec<(String, String)>,
 }
 
 impl EnvVars {
     fn new() -> Self {
         EnvVars {
             library_search_paths: Vec::new(),
+            extra_vars: Vec::new(),
         }
     }
 
@@ -36,6 +38,10 @@ impl EnvVars {
 
         let value = self.library_search_paths.join(separator);
         f(env_var, &value);
+
+        for (key, value) in &self.extra_vars {
+            f(key, value);
+        }
     }
 
     fn with_additional_library_path(&self, path: String) -> Self {
@@ -43,6 +49,14 @@ impl EnvVars {
         new_env_vars.add_library_path(path);
         new_env_vars
     }
+
+    fn with_var(&self, key: &str, value: &str) -> Self {
+        let mut new_env_vars = self.clone();
+        new_env_vars
+            .extra_vars
+            .push((key.to_string(), value.to_string()));
+        new_env_vars
+    }
 }
 
 fn set_env_variables() -> EnvVars {
@@ -197,16 +211,15 @@ fn run_command(command: &[&str], env_vars: &EnvVars) -> io::Result<(bool, String
     Ok((status.success(), output))
 }
 
-fn check_feature_mismatch(output: &str) -> bool {
-    output.contains("Feature mismatch for crate")
-}
-
 struct TestCase {
     name: &'static str,
     build_command: &'static [&'static str],
     run_command: &'static [&'static str],
+    run_env: &'static [(&'static str, &'static str)],
     expected_result: &'static str,
-    check_feature_mismatch: bool,
+    expected_error: Option<&'static str>,
+    /// For tests expected to succeed, something the output must contain
+    expected_output: Option<&'static str>,
     allowed_to_fail: bool,
 }
 
@@ -220,10 +233,81 @@ static TEST_CASES: &[TestCase] = &[
             "test-crates/samplebin/Cargo.toml",
         ],
         run_command: &["./test-crates/samplebin/target/debug/samplebin"],
+        run_env: &[],
         expected_result: "success",
-        check_feature_mismatch: false,
+        expected_error: None,
+        expected_output: None,
         allowed_to_fail: false,
     },
+    // relies on the modules built by the previous test
+    TestCase {
+        name: "rubicon-check accepts the debug build",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "rubicon/Cargo.toml",
+            "--features",
+            "cli",
+        ],
+        run_command: &[
+            "./rubicon/target/debug/rubicon-check",
+            "test-crates/samplebin/target/debug/samplebin",
+            "test-crates/mod_a/target/debug/libmod_a.so",
+            "test-crates/mod_b/target/debug/libmod_b.so",
+        ],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: None,
+        allowed_to_fail: !cfg!(target_os = "linux"),
+    },
+    TestCase {
+        name: "rubicon-check lists the symbols of the debug build",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "rubicon/Cargo.toml",
+            "--features",
+            "cli",
+        ],
+        run_command: &[
+            "./rubicon/target/debug/rubicon-check",
+            "symbols",
+            "test-crates/samplebin/target/debug/samplebin",
+            "test-crates/mod_a/target/debug/libmod_a.so",
+        ],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        // mod_a's imports are found in the dylib samplebin links against
+        expected_output: Some("mokio_0_1__MOKIO_TL1__RUBICON_EXPORT: from libexports.so"),
+        allowed_to_fail: !cfg!(target_os = "linux"),
+    },
+    TestCase {
+        name: "rubicon-check lists the symbols of a module on its own",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "rubicon/Cargo.toml",
+            "--features",
+            "cli",
+        ],
+        run_command: &[
+            "./rubicon/target/debug/rubicon-check",
+            "symbols",
+            "test-crates/mod_b/target/debug/libmod_b.so",
+        ],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: Some(
+            "mokio_0_1__MOKIO_TL1__RUBICON_EXPORT: unresolved (pass the host binary)",
+        ),
+        allowed_to_fail: !cfg!(target_os = "linux"),
+    },
     TestCase {
         name: "Tests pass (release)",
         build_command: &[
@@ -234,10 +318,38 @@ static TEST_CASES: &[TestCase] = &[
             "--release",
         ],
         run_command: &["./test-crates/samplebin/target/release/samplebin"],
+        run_env: &[],
         expected_result: "success",
-        check_feature_mismatch: false,
+        expected_error: None,
+        expected_output: None,
         allowed_to_fail: false,
     },
+    TestCase {
+        name: "mod_a's own tests pass with rubicon-fallback",
+        build_command: &[
+            "cargo",
+            "test",
+            "--no-run",
+            "--manifest-path",
+            "test-crates/mod_a/Cargo.toml",
+            "--features",
+            "rubicon-fallback",
+        ],
+        // there's no exporter in a test binary, not even for `static mut`s
+        run_command: &[
+            "cargo",
+            "test",
+            "--manifest-path",
+            "test-crates/mod_a/Cargo.toml",
+            "--features",
+            "rubicon-fallback",
+        ],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: Some("test tests::globals_fall_back_to_local_instances ... ok"),
+        allowed_to_fail: cfg!(windows),
+    },
     TestCase {
         name: "Bin stable, mod_a nightly (should fail)",
         build_command: &[
@@ -251,9 +363,12 @@ static TEST_CASES: &[TestCase] = &[
             "./test-crates/samplebin/target/debug/samplebin",
             "--channel:mod_a=nightly",
         ],
+        run_env: &[],
         expected_result: "fail",
-        check_feature_mismatch: true,
-        allowed_to_fail: cfg!(target_os = "linux"),
+        // the binary's `rustc-channel`, highlighted as a mismatch
+        expected_error: Some("\x1b[32mstable\x1b[0m"),
+        expected_output: None,
+        allowed_to_fail: false,
     },
     TestCase {
         name: "Bin nightly, mod_a stable (should fail)",
@@ -268,9 +383,12 @@ static TEST_CASES: &[TestCase] = &[
             "./test-crates/samplebin/target/debug/samplebin",
             "--channel:mod_a=stable",
         ],
+        run_env: &[],
         expected_result: "fail",
-        check_feature_mismatch: true,
-        allowed_to_fail: cfg!(target_os = "linux"),
+        // the binary's `rustc-channel`, highlighted as a mismatch
+        expected_error: Some("\x1b[32mnightly\x1b[0m"),
+        expected_output: None,
+        allowed_to_fail: false,
     },
     TestCase {
         name: "All nightly (should work)",
@@ -286,8 +404,99 @@ static TEST_CASES: &[TestCase] = &[
             "--channel:mod_a=nightly",
             "--channel:mod_b=nightly",
         ],
+        run_env: &[],
         expected_result: "success",
-        check_feature_mismatch: false,
+        expected_error: None,
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "Bin debug, mod_a release (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--profile:mod_a=release",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("Feature mismatch for crate"),
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "mod_a built with another opt-level (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--opt-level:mod_a=1",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("Feature mismatch for crate"),
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "mod_a built with extra RUSTFLAGS (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--rustflags:mod_a=-Cforce-frame-pointers=yes",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("Feature mismatch for crate"),
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "Bin expects an extern \"C\" init (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--extern-c-init",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("SignatureMismatch"),
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "Benchmark imported thread-locals against std::thread_local! (release)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+            "--release",
+        ],
+        // fails if imported thread-locals are more than 2x slower
+        run_command: &["./test-crates/samplebin/target/release/samplebin", "--bench"],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: Some("imported thread-local: "),
         allowed_to_fail: false,
     },
     TestCase {
@@ -300,8 +509,10 @@ static TEST_CASES: &[TestCase] = &[
             "test-crates/samplebin/Cargo.toml",
         ],
         run_command: &["./test-crates/samplebin/target/debug/samplebin"],
+        run_env: &[],
         expected_result: "fail",
-        check_feature_mismatch: true,
+        expected_error: Some("Feature mismatch for crate"),
+        expected_output: None,
         allowed_to_fail: false,
     },
     TestCase {
@@ -316,10 +527,90 @@ static TEST_CASES: &[TestCase] = &[
             "./test-crates/samplebin/target/debug/samplebin",
             "--features:mod_a=mokio/timer",
         ],
+        run_env: &[],
         expected_result: "fail",
-        check_feature_mismatch: true,
+        expected_error: Some("Feature mismatch for crate"),
+        expected_output: None,
         allowed_to_fail: false,
     },
+    // relies on the modules built by the previous test
+    TestCase {
+        name: "rubicon-check rejects mod_a with mokio-timer feature (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "rubicon/Cargo.toml",
+            "--features",
+            "cli",
+        ],
+        run_command: &[
+            "./rubicon/target/debug/rubicon-check",
+            "test-crates/samplebin/target/debug/samplebin",
+            "test-crates/mod_a/target/debug/libmod_a.so",
+            "test-crates/mod_b/target/debug/libmod_b.so",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("Feature mismatch for crate"),
+        expected_output: None,
+        allowed_to_fail: !cfg!(target_os = "linux"),
+    },
+    TestCase {
+        name: "mod_a is opened with dlopen, its constructor runs the check",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &["./test-crates/samplebin/target/debug/samplebin", "--dlopen"],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: None,
+        allowed_to_fail: cfg!(windows),
+    },
+    TestCase {
+        name: "mod_a has mokio-timer feature, opened with dlopen (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=mokio/timer",
+            "--dlopen",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("isn't being loaded by Module::load"),
+        expected_output: None,
+        allowed_to_fail: cfg!(windows),
+    },
+    TestCase {
+        name: "mod_a has mokio-timer feature, opened with dlopen, bin's handler asks for a panic (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=mokio/timer",
+            "--dlopen",
+            "--on-mismatch=list-modules",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        // the panic is raised in the module, and caught by its constructor
+        expected_error: Some("isn't being loaded by Module::load"),
+        expected_output: None,
+        allowed_to_fail: cfg!(windows),
+    },
     TestCase {
         name: "mod_b has mokio-timer feature (should fail)",
         build_command: &[
@@ -332,8 +623,10 @@ static TEST_CASES: &[TestCase] = &[
             "./test-crates/samplebin/target/debug/samplebin",
             "--features:mod_b=mokio/timer",
         ],
+        run_env: &[],
         expected_result: "fail",
-        check_feature_mismatch: true,
+        expected_error: Some("Feature mismatch for crate"),
+        expected_output: None,
         allowed_to_fail: false,
     },
     TestCase {
@@ -349,8 +642,10 @@ static TEST_CASES: &[TestCase] = &[
             "--features:mod_a=mokio/timer",
             "--features:mod_b=mokio/timer",
         ],
+        run_env: &[],
         expected_result: "fail",
-        check_feature_mismatch: true,
+        expected_error: Some("Feature mismatch for crate"),
+        expected_output: None,
         allowed_to_fail: false,
     },
     TestCase {
@@ -367,8 +662,277 @@ static TEST_CASES: &[TestCase] = &[
             "--features:mod_a=mokio/timer",
             "--features:mod_b=mokio/timer",
         ],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "mod_a has mokio-instrumented feature (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=mokio/instrumented",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("Layout mismatch for global"),
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "mod_a resolves globals with dlsym",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=rubicon-dlsym",
+        ],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: None,
+        allowed_to_fail: cfg!(windows),
+    },
+    TestCase {
+        name: "mod_a resolves globals with dlsym, one of them isn't exported (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=missing-global",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        // nothing exports the crate's compatibility info either, which is found first
+        expected_error: Some(
+            "MissingExport { path: \"../mod_a/target/debug/libmod_a.so\", crate_name: \"mod_a\", symbol: \"mod_a_0_1__compatibility_info\" }",
+        ),
+        expected_output: None,
+        allowed_to_fail: cfg!(windows),
+    },
+    TestCase {
+        name: "mod_a resolves globals with dlsym, one of them isn't exported, opened with dlopen (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=missing-global",
+            "--dlopen",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some(
+            "imports globals from crate mod_a, but no loaded shared object exports mod_a_0_1__compatibility_info",
+        ),
+        expected_output: None,
+        allowed_to_fail: cfg!(windows),
+    },
+    TestCase {
+        name: "mod_a falls back to a local instance of a global that isn't exported",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=missing-global,rubicon-fallback",
+        ],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: None,
+        allowed_to_fail: cfg!(windows),
+    },
+    TestCase {
+        name:
+            "RUBICON_TRACE shows mod_a using its local instance of the global that isn't exported",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=missing-global,rubicon-fallback",
+        ],
+        run_env: &[("RUBICON_TRACE", "1")],
         expected_result: "success",
-        check_feature_mismatch: false,
+        expected_error: None,
+        expected_output: Some("in ../mod_a/target/debug/libmod_a.so (compatibility check passed)"),
+        allowed_to_fail: cfg!(windows),
+    },
+    TestCase {
+        name: "RUBICON_TRACE shows mod_b using the globals exported by libexports.so",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &["./test-crates/samplebin/target/debug/samplebin"],
+        run_env: &[("RUBICON_TRACE", "1")],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: Some("libmod_b.so accesses mokio::MOKIO_PL1 at"),
+        allowed_to_fail: cfg!(windows),
+    },
+    TestCase {
+        name: "mod_b frees a buffer allocated by mod_a, with the host's allocator",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--cross-free",
+        ],
+        run_env: &[],
+        expected_result: "success",
+        expected_error: None,
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "mod_b frees a buffer allocated by mod_a, with another allocator (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=debug-allocator",
+            "--features:mod_b=debug-allocator",
+            "--cross-free",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("libmod_a.so, with another allocator"),
+        expected_output: None,
+        allowed_to_fail: cfg!(windows),
+    },
+    TestCase {
+        name: "mod_a has mokio-timer feature, bin aborts on mismatch (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=mokio/timer",
+            "--on-mismatch=abort",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("incompatible shared object, aborting"),
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "mod_a has mokio-timer feature, bin registers panic_on_mismatch (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=mokio/timer",
+            "--on-mismatch=panic",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        // the module panics, and `Module::load` returns an error instead of aborting
+        expected_error: Some("Incompatible { path: \"../mod_a/target/debug/libmod_a.so\""),
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "mod_a has mokio-timer feature, loaded twice (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=mokio/timer",
+            "--load-twice",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        // the second load fails like the first, rather than returning the module
+        expected_error: Some(
+            "on an `Err` value: Incompatible { path: \"../mod_a/target/debug/libmod_a.so\"",
+        ),
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "mod_a has mokio-timer feature, bin's handler lists loaded modules (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=mokio/timer",
+            "--on-mismatch=list-modules",
+        ],
+        run_env: &[],
+        expected_result: "fail",
+        expected_error: Some("mismatch while these modules were loaded: []"),
+        expected_output: None,
+        allowed_to_fail: false,
+    },
+    TestCase {
+        name: "mod_a has mokio-timer feature, RUBICON_COMPAT=abort (should fail)",
+        build_command: &[
+            "cargo",
+            "build",
+            "--manifest-path",
+            "test-crates/samplebin/Cargo.toml",
+        ],
+        run_command: &[
+            "./test-crates/samplebin/target/debug/samplebin",
+            "--features:mod_a=mokio/timer",
+            "--on-mismatch=panic",
+        ],
+        run_env: &[("RUBICON_COMPAT", "abort")],
+        expected_result: "fail",
+        expected_error: Some("incompatible shared object, aborting"),
+        expected_output: None,
         allowed_to_fail: false,
     },
 ];
@@ -436,18 +1000,31 @@ fn run_tests() -> io::Result<()> {
             .join("samplebin")
             .join("target")
             .join(profile);
-        let env_vars =
+        let mut env_vars =
             env_vars.with_additional_library_path(additional_path.to_string_lossy().into_owned());
+        for (key, value) in test.run_env {
+            env_vars = env_vars.with_var(key, value);
+        }
 
         let (success, output) = run_command(test.run_command, &env_vars)?;
 
         match (test.expected_result, success) {
-            ("success", true) => println!("✅ \x1b[1;32mTest passed as expected.\x1b[0m"),
-            ("fail", false) if test.check_feature_mismatch && check_feature_mismatch(&output) => {
-                println!("✅ \x1b[1;33mTest failed with feature mismatch as expected.\x1b[0m")
+            ("success", true)
+                if test
+                    .expected_output
+                    .is_none_or(|expected| output.contains(expected)) =>
+            {
+                println!("✅ \x1b[1;32mTest passed as expected.\x1b[0m")
+            }
+            ("fail", false)
+                if test
+                    .expected_error
+                    .is_some_and(|error| output.contains(error)) =>
+            {
+                println!("✅ \x1b[1;33mTest failed with the expected error.\x1b[0m")
             }
-            ("fail", false) if test.check_feature_mismatch => {
-                eprintln!("❌ \x1b[1;31mTest failed, but not with the expected feature mismatch error.\x1b[0m");
+            ("fail", false) if test.expected_error.is_some() => {
+                eprintln!("❌ \x1b[1;31mTest failed, but not with the expected error.\x1b[0m");
                 if test.allowed_to_fail || cfg!(windows) {
                     println!("⚠️ \x1b[1;33mTest was allowed to fail.\x1b[0m");
                 } else {